@@ -1,21 +1,78 @@
+use egui::text::{LayoutJob, TextFormat};
 use egui::{Color32, TextStyle};
 use egui_extras::{Size, StripBuilder};
 
-use crate::query_parser::search_energies;
+use crate::background::{default_background, BackgroundLine};
+use crate::calibration::{CalibrationPoint, EnergyCalibration, REFERENCE_LINES};
+use crate::peaks::{find_peaks, Peak, PeakSearch};
+use crate::query_parser::{
+    background_lines, line_numbers, parse_duration, parse_query, Diagnostic, Energy,
+};
+use crate::resolution::ResolutionModel;
+use crate::results_table::ResultsTable;
+use crate::search::{CandidateOrder, SearchError, SearchOptions, SearchResult};
+use crate::spectrum::{read_spectrum, Spectrum};
+use crate::spectrum_plot::{LinePlot, SpectrumPlot};
 
 const GAMMA_EXAMPLE_STRING: &str = "# This is a comment and is not considered for the query
 # The energy can be in eV, keV, and MeV
 
 6.96 keV 1% # Uncertainty is expressed in percentage
 215.9 keV 1%
-231.6 keV 0.5%
-0.2389 MeV 0.5%
+231.6 keV ± 1.2 keV # or as an absolute value
+0.2389(12) MeV # or in units of the last digits, optionally followed by a multiplier like 2σ
+
+# Lines starting with \"maybe\" are optional: they raise the score of the decays explaining them
+# but do not count against the others, e.g. maybe 53.2 keV 1%
+
+# Intensities can be bounded per 100 decays (I>10%) or relative to another line (I/L1>0.5),
+# lines being numbered as in the editor
+
+# Lines such as T1/2 > 1 h hide the decays of short-lived parents
+
+# A line can name its radiation type to mix types in one search, e.g. alpha 5.304 MeV 0.5%
+
+# Dropping a spectrum file on the window replaces the query with the peaks fitted in it,
+# each matched within a few standard uncertainties of its centroid, e.g. 661.657 keV ± 0.00412 keV 3.00σ
 
 # It is possible to show all radiation records from a decay dataset or only the ones that match the query";
 
 const ALPHA_EXAMPLE_STRING: &str = "4.149 MeV 0.5%
 4.198 MeV 1%";
 
+/// Lays out the query, underlining the problems found in it by the last search.
+fn query_layout_job(ui: &egui::Ui, text: &str, diagnostics: &[Diagnostic]) -> LayoutJob {
+    let normal = TextFormat {
+        font_id: TextStyle::Body.resolve(ui.style()),
+        color: ui.visuals().text_color(),
+        ..Default::default()
+    };
+    let error_color = ui.visuals().error_fg_color;
+    let error = TextFormat {
+        underline: egui::Stroke::new(1.5, error_color),
+        color: error_color,
+        ..normal.clone()
+    };
+    let mut job = LayoutJob::default();
+    for (i, line) in text.split_inclusive('\n').enumerate() {
+        let Some(d) = diagnostics.iter().find(|d| d.line == i + 1) else {
+            job.append(line, 0.0, normal.clone());
+            continue;
+        };
+        // Empty spans (missing parts) point at the end of the line
+        let start = d.span.start.min(line.trim_end().len());
+        let end = d.span.end.max(start + 1).min(line.trim_end().len());
+        let (start, end) = match line.get(start..end) {
+            Some(s) if !s.is_empty() => (start, end),
+            _ => (0, line.trim_end().len()),
+        };
+        job.append(&line[..start], 0.0, normal.clone());
+        job.append(&line[start..end], 0.0, error.clone());
+        job.append(&line[end..], 0.0, normal.clone());
+    }
+    job
+}
+
 #[derive(PartialEq, serde::Deserialize, serde::Serialize)]
 #[serde(tag = "type")]
 pub enum PrintMode {
@@ -23,19 +80,48 @@ pub enum PrintMode {
     OnlyMatches,
 }
 
-#[derive(PartialEq, serde::Deserialize, serde::Serialize)]
+#[derive(
+    Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, serde::Deserialize, serde::Serialize,
+)]
 #[serde(tag = "type")]
 pub enum RadiationType {
     Gamma,
     Alpha,
+    /// Beta continua (β- and β+), matched by their endpoint energy.
+    Beta,
+    /// K and L X-rays following electron capture or internal conversion.
+    XRay,
+    ConversionElectron,
+    Auger,
+}
+
+impl std::fmt::Display for RadiationType {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let s = match self {
+            RadiationType::Gamma => "gamma",
+            RadiationType::Alpha => "alpha",
+            RadiationType::Beta => "beta",
+            RadiationType::XRay => "xray",
+            RadiationType::ConversionElectron => "ce",
+            RadiationType::Auger => "auger",
+        };
+        write!(f, "{s}")
+    }
 }
 
-impl PartialEq<String> for RadiationType {
-    fn eq(&self, other: &String) -> bool {
-        matches!(
-            (self, other.as_str()),
-            (RadiationType::Gamma, "G") | (RadiationType::Alpha, "A")
-        )
+impl std::str::FromStr for RadiationType {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "gamma" | "g" => Ok(RadiationType::Gamma),
+            "alpha" | "a" => Ok(RadiationType::Alpha),
+            "beta" | "b" => Ok(RadiationType::Beta),
+            "xray" | "x-ray" | "x" => Ok(RadiationType::XRay),
+            "ce" | "conversion-electron" => Ok(RadiationType::ConversionElectron),
+            "auger" => Ok(RadiationType::Auger),
+            _ => Err(format!("unknown radiation type: {s}")),
+        }
     }
 }
 
@@ -49,6 +135,41 @@ pub struct TemplateApp {
     message_to_user: String,
     search_results: String,
     radiation_type: RadiationType,
+    search_options: SearchOptions,
+    resolution: ResolutionModel,
+    /// Problems found in the query by the last search, highlighted until the query is edited.
+    #[serde(skip)]
+    diagnostics: Vec<Diagnostic>,
+    /// Results of the last search, shown as a table; `search_results` then summarizes them.
+    #[serde(skip)]
+    results: Option<SearchResult>,
+    /// Energies of the query of the last search, shaded in the spectrum plot.
+    #[serde(skip)]
+    query_energies: Vec<Energy>,
+    results_table: ResultsTable,
+    /// Shortest half-life of the parents shown, e.g. "1 h"; empty for no bound.
+    min_half_life: String,
+    /// Time since the end of the irradiation, e.g. "7 d"; empty to ignore decay and ingrowth.
+    cooling_time: String,
+    peak_search: PeakSearch,
+    /// Last spectrum dropped on the window, with its file name.
+    #[serde(skip)]
+    spectrum: Option<(String, Spectrum)>,
+    /// Peaks found in `spectrum`, offered for calibration.
+    #[serde(skip)]
+    peaks: Vec<Peak>,
+    calibration: EnergyCalibration,
+    #[serde(skip)]
+    show_calibration: bool,
+    /// Energy typed for assigning a peak to a line missing from the reference lines.
+    #[serde(skip)]
+    other_energy: f64,
+    /// Plot the spectrum above the results.
+    plot_spectrum: bool,
+    spectrum_plot: SpectrumPlot,
+    /// Plot the records of a candidate above the results.
+    plot_lines: bool,
+    line_plot: LinePlot,
 }
 
 impl Default for TemplateApp {
@@ -59,6 +180,24 @@ impl Default for TemplateApp {
             message_to_user: "Waiting for input".to_string(),
             search_results: "No results".to_string(),
             radiation_type: RadiationType::Gamma,
+            search_options: SearchOptions::default(),
+            resolution: ResolutionModel::None,
+            diagnostics: Vec::new(),
+            results: None,
+            query_energies: Vec::new(),
+            results_table: ResultsTable::default(),
+            min_half_life: String::new(),
+            cooling_time: String::new(),
+            peak_search: PeakSearch::default(),
+            spectrum: None,
+            peaks: Vec::new(),
+            calibration: EnergyCalibration::default(),
+            show_calibration: false,
+            other_energy: 0.0,
+            plot_spectrum: true,
+            spectrum_plot: SpectrumPlot::default(),
+            plot_lines: true,
+            line_plot: LinePlot::default(),
         }
     }
 }
@@ -77,6 +216,414 @@ impl TemplateApp {
 
         Default::default()
     }
+
+    /// Detector resolution settings, shown in the menu bar.
+    fn resolution_menu(&mut self, ui: &mut egui::Ui) {
+        ui.label("Window for lines written without an uncertainty:");
+        let model = &mut self.resolution;
+        if ui
+            .radio(matches!(model, ResolutionModel::None), "exact energy")
+            .clicked()
+        {
+            *model = ResolutionModel::None;
+        }
+        if ui
+            .radio(matches!(model, ResolutionModel::Hpge { .. }), "HPGe")
+            .clicked()
+            && !matches!(model, ResolutionModel::Hpge { .. })
+        {
+            *model = ResolutionModel::default_hpge();
+        }
+        if ui
+            .radio(
+                matches!(model, ResolutionModel::Scintillator { .. }),
+                "NaI(Tl) / LaBr3",
+            )
+            .clicked()
+            && !matches!(model, ResolutionModel::Scintillator { .. })
+        {
+            *model = ResolutionModel::default_scintillator();
+        }
+        ui.separator();
+        match model {
+            ResolutionModel::None => {}
+            ResolutionModel::Hpge { a, b, c } => {
+                ui.label("FWHM = sqrt(a + b·E + c·E²), in keV");
+                ui.horizontal(|ui| {
+                    ui.label("a:");
+                    ui.add(egui::DragValue::new(a).speed(0.01));
+                    ui.label("b:");
+                    ui.add(egui::DragValue::new(b).speed(1e-5).max_decimals(6));
+                    ui.label("c:");
+                    ui.add(egui::DragValue::new(c).speed(1e-8).max_decimals(9));
+                });
+            }
+            ResolutionModel::Scintillator { percent_at_662 } => {
+                ui.horizontal(|ui| {
+                    ui.label("FWHM at 662 keV:");
+                    ui.add(
+                        egui::DragValue::new(percent_at_662)
+                            .speed(0.05)
+                            .clamp_range(0.1..=50.0)
+                            .suffix(" %"),
+                    );
+                });
+            }
+        }
+        if !matches!(model, ResolutionModel::None) {
+            ui.label(format!(
+                "FWHM at 662 keV: {:.2} keV (matched within ± FWHM/2, or ± kσ with a kσ suffix)",
+                model.fwhm(662.0)
+            ));
+        }
+    }
+
+    fn spectrum_menu(&mut self, ui: &mut egui::Ui) {
+        ui.label("Drop a spectrum (.Spe, .Chn, .n42 or two-column .csv) on the window");
+        ui.label("to search the peaks found in it.");
+        ui.separator();
+        ui.horizontal(|ui| {
+            ui.label("Peak FWHM:");
+            ui.add(
+                egui::DragValue::new(&mut self.peak_search.fwhm)
+                    .speed(0.1)
+                    .clamp_range(1.0..=100.0)
+                    .suffix(" channels"),
+            );
+        });
+        ui.horizontal(|ui| {
+            ui.label("Threshold:");
+            ui.add(
+                egui::DragValue::new(&mut self.peak_search.threshold)
+                    .speed(0.1)
+                    .clamp_range(1.0..=100.0)
+                    .suffix(" σ"),
+            );
+        });
+        ui.horizontal(|ui| {
+            ui.label("Match window: ±");
+            ui.add(
+                egui::DragValue::new(&mut self.peak_search.sigmas)
+                    .speed(0.1)
+                    .clamp_range(0.5..=100.0)
+                    .suffix(" σ"),
+            )
+            .on_hover_text(
+                "In standard uncertainties of the peak energy (centroid and calibration), \
+                 at least a tenth of a channel",
+            );
+        });
+        if ui.button("Energy calibration…").clicked() {
+            self.show_calibration = true;
+            ui.close_menu();
+        }
+        if let Some((name, _)) = &self.spectrum {
+            if ui
+                .button(format!("Search the peaks of {name} again"))
+                .clicked()
+            {
+                self.search_peaks();
+                ui.close_menu();
+            }
+        }
+    }
+
+    fn load_spectrum(&mut self, name: &str, bytes: &[u8]) {
+        match read_spectrum(name, bytes) {
+            Ok(spectrum) => {
+                self.spectrum = Some((name.to_string(), spectrum));
+                self.search_peaks();
+            }
+            Err(e) => {
+                self.results = None;
+                self.search_results = format!("Cannot read {name}: {e}");
+            }
+        }
+    }
+
+    /// Replaces the query with the peaks found in the spectrum, then searches them.
+    fn search_peaks(&mut self) {
+        let Some((name, spectrum)) = &self.spectrum else {
+            return;
+        };
+        if let Err(e) = self.peak_search.check() {
+            self.results = None;
+            self.search_results = e;
+            return;
+        }
+        let mut spectrum = spectrum.clone();
+        self.calibration.apply(&mut spectrum);
+        self.peaks = find_peaks(&spectrum, &self.peak_search);
+        if spectrum.calibration.is_empty() {
+            self.results = None;
+            self.search_results = format!(
+                "{name} has no energy calibration: assign some of its peaks to known lines"
+            );
+            self.show_calibration = true;
+            return;
+        }
+        let lines: Vec<String> = self.peaks.iter().filter_map(Peak::query_line).collect();
+        self.user_query = format!(
+            "# {} peaks found in {name}\n{}",
+            lines.len(),
+            lines.join("\n")
+        );
+        self.diagnostics.clear();
+        self.search();
+    }
+
+    fn calibration_window(&mut self, ctx: &egui::Context) {
+        let mut open = self.show_calibration;
+        let mut apply = false;
+        egui::Window::new("Energy calibration")
+            .open(&mut open)
+            .show(ctx, |ui| {
+                let calibration = &mut self.calibration;
+                ui.label("Peaks found in the spectrum:");
+                if self.peaks.is_empty() {
+                    ui.weak("none, drop a spectrum file on the window");
+                }
+                egui::ScrollArea::vertical()
+                    .id_source("calibration_peaks")
+                    .max_height(200.0)
+                    .show(ui, |ui| {
+                        egui::Grid::new("calibration_peaks")
+                            .striped(true)
+                            .show(ui, |ui| {
+                                for peak in &self.peaks {
+                                    ui.label(format!("channel {:.2}", peak.channel));
+                                    match peak.energy {
+                                        Some(e) => ui.label(format!("{e:.3} keV")),
+                                        None => ui.label(""),
+                                    };
+                                    match &peak.fit {
+                                        Some(fit) => ui.label(format!("area {:.0}", fit.area)),
+                                        None => ui.label(format!("{:.1}σ", peak.significance)),
+                                    };
+                                    ui.menu_button("assign to", |ui| {
+                                        let mut assigned = None;
+                                        for (name, energy) in REFERENCE_LINES {
+                                            if ui.button(format!("{name} {energy} keV")).clicked() {
+                                                assigned = Some(energy);
+                                            }
+                                        }
+                                        ui.horizontal(|ui| {
+                                            ui.add(
+                                                egui::DragValue::new(&mut self.other_energy)
+                                                    .speed(0.1)
+                                                    .clamp_range(0.0..=f64::INFINITY)
+                                                    .suffix(" keV"),
+                                            );
+                                            if ui.button("other line").clicked() {
+                                                assigned = Some(self.other_energy);
+                                            }
+                                        });
+                                        if let Some(energy) = assigned {
+                                            calibration.points.push(CalibrationPoint {
+                                                channel: peak.channel,
+                                                energy,
+                                            });
+                                            ui.close_menu();
+                                        }
+                                    });
+                                    ui.end_row();
+                                }
+                            });
+                    });
+                ui.separator();
+                ui.horizontal(|ui| {
+                    ui.radio_value(&mut calibration.degree, 1, "linear");
+                    ui.radio_value(&mut calibration.degree, 2, "quadratic");
+                });
+                let residuals = calibration.residuals();
+                let mut removed = None;
+                egui::Grid::new("calibration_points")
+                    .striped(true)
+                    .show(ui, |ui| {
+                        ui.label("Channel");
+                        ui.label("Energy (keV)");
+                        ui.label("Residual (keV)");
+                        ui.end_row();
+                        for (i, point) in calibration.points.iter_mut().enumerate() {
+                            ui.add(egui::DragValue::new(&mut point.channel).speed(0.01));
+                            ui.add(egui::DragValue::new(&mut point.energy).speed(0.001));
+                            match residuals[i] {
+                                Some(r) => ui.label(format!("{r:+.3}")),
+                                None => ui.label(""),
+                            };
+                            if ui.small_button("🗑").clicked() {
+                                removed = Some(i);
+                            }
+                            ui.end_row();
+                        }
+                    });
+                if let Some(i) = removed {
+                    calibration.points.remove(i);
+                }
+                // Cheap enough to redo on every frame, so edits show at once
+                calibration.fit();
+                ui.label(calibration.to_string());
+                if let Some(rms) = calibration.rms_residual() {
+                    ui.label(format!("RMS residual: {rms:.3} keV"));
+                }
+                ui.checkbox(
+                    &mut calibration.replace_file_calibration,
+                    "also replace the calibration saved in spectrum files",
+                );
+                // Why the calibration cannot be applied, shown next to the button
+                let blocked = match self.spectrum.as_ref().map(|(_, s)| &s.calibration) {
+                    _ if calibration.coefficients.is_empty() => Some(format!(
+                        "assign at least {} peaks to known energies",
+                        calibration.degree + 1
+                    )),
+                    None => Some("drop a spectrum file on the window".to_string()),
+                    _ if self.peaks.is_empty() => {
+                        Some("no peaks found in the spectrum".to_string())
+                    }
+                    Some(c) if !calibration.replaces(c) => Some(
+                        "the spectrum file has its own calibration, \
+                         check \"also replace\" to use this one"
+                            .to_string(),
+                    ),
+                    _ => None,
+                };
+                ui.horizontal(|ui| {
+                    apply = ui
+                        .add_enabled(
+                            blocked.is_none(),
+                            egui::Button::new("Apply and search the peaks"),
+                        )
+                        .clicked();
+                    if ui.button("Clear").clicked() {
+                        calibration.points.clear();
+                    }
+                });
+                if let Some(reason) = blocked {
+                    ui.weak(reason);
+                }
+            });
+        self.show_calibration = open;
+        if apply {
+            self.search_peaks();
+        }
+    }
+
+    fn background_menu(&mut self, ui: &mut egui::Ui) {
+        let options = &mut self.search_options;
+        ui.checkbox(&mut options.flag_background, "flag background lines")
+            .on_hover_text(
+                "Query lines at these energies are reported as background and \
+                 need not be explained by the decays found",
+            );
+        ui.separator();
+        let mut removed = None;
+        egui::ScrollArea::vertical()
+            .max_height(300.0)
+            .show(ui, |ui| {
+                egui::Grid::new("background_lines")
+                    .striped(true)
+                    .show(ui, |ui| {
+                        ui.label("");
+                        ui.label("Source");
+                        ui.label("Energy (keV)");
+                        ui.label("± (keV)");
+                        ui.end_row();
+                        for (i, line) in options.background.iter_mut().enumerate() {
+                            ui.checkbox(&mut line.enabled, "");
+                            ui.add(egui::TextEdit::singleline(&mut line.name).desired_width(90.0));
+                            ui.add(
+                                egui::DragValue::new(&mut line.energy)
+                                    .speed(0.01)
+                                    .clamp_range(0.0..=f64::INFINITY),
+                            );
+                            ui.add(
+                                egui::DragValue::new(&mut line.tolerance)
+                                    .speed(0.01)
+                                    .clamp_range(0.0..=f64::INFINITY),
+                            );
+                            if ui.small_button("🗑").clicked() {
+                                removed = Some(i);
+                            }
+                            ui.end_row();
+                        }
+                    });
+            });
+        if let Some(i) = removed {
+            options.background.remove(i);
+        }
+        ui.horizontal(|ui| {
+            if ui.button("Add line").clicked() {
+                options.background.push(BackgroundLine {
+                    name: String::new(),
+                    energy: 0.0,
+                    tolerance: 0.5,
+                    enabled: true,
+                });
+            }
+            if ui.button("Restore defaults").clicked() {
+                options.background = default_background();
+            }
+        });
+    }
+
+    fn search(&mut self) {
+        self.diagnostics.clear();
+        self.results = None;
+        self.query_energies.clear();
+        self.search_options.half_life.min = match self.min_half_life.trim() {
+            "" => None,
+            s => match parse_duration(s) {
+                Ok(seconds) => Some(seconds),
+                Err(e) => {
+                    self.search_results = format!("Invalid minimum half-life: {e}");
+                    return;
+                }
+            },
+        };
+        self.search_options.cooling_time = match self.cooling_time.trim() {
+            "" => None,
+            s => match parse_duration(s) {
+                Ok(seconds) => Some(seconds),
+                Err(e) => {
+                    self.search_results = format!("Invalid time since irradiation: {e}");
+                    return;
+                }
+            },
+        };
+        let results = parse_query(&self.user_query, &self.resolution).and_then(|q| {
+            self.query_energies = q.energies.clone();
+            q.search(&self.radiation_type, &self.search_options)
+        });
+        self.search_results = match results {
+            Ok(r) if r.candidates.is_empty() => "No results found".to_string(),
+            Ok(r) => {
+                let mut summary = match r.candidates.len() {
+                    1 => "1 decay found".to_string(),
+                    n => format!("{n} decays found"),
+                };
+                if !r.unmatched_lines.is_empty() {
+                    summary += &format!(
+                        "; query lines not explained by any decay: {}",
+                        line_numbers(&r.query_lines, &r.unmatched_lines)
+                    );
+                }
+                if !r.background_lines.is_empty() {
+                    summary += &format!(
+                        "; background: {}",
+                        background_lines(&r.query_lines, &r.background_lines)
+                    );
+                }
+                self.results = Some(r);
+                summary
+            }
+            Err(e) => {
+                if let SearchError::InvalidQuery(diagnostics) = &e {
+                    self.diagnostics = diagnostics.clone();
+                }
+                e.to_string()
+            }
+        };
+    }
 }
 
 impl eframe::App for TemplateApp {
@@ -90,6 +637,29 @@ impl eframe::App for TemplateApp {
         // Put your widgets into a `SidePanel`, `TopBottomPanel`, `CentralPanel`, `Window` or `Area`.
         // For inspiration and more examples, go to https://emilk.github.io/egui
 
+        // Spectra dropped on the window: bytes on the web, paths natively
+        let dropped = ctx.input(|i| i.raw.dropped_files.clone());
+        if let Some(file) = dropped.last() {
+            let name = match &file.path {
+                Some(path) if file.name.is_empty() => path
+                    .file_name()
+                    .map(|n| n.to_string_lossy().into_owned())
+                    .unwrap_or_default(),
+                _ => file.name.clone(),
+            };
+            let bytes = match (&file.bytes, &file.path) {
+                (Some(bytes), _) => Ok(bytes.to_vec()),
+                (None, Some(path)) => std::fs::read(path),
+                (None, None) => Ok(vec![]),
+            };
+            match bytes {
+                Ok(bytes) => self.load_spectrum(&name, &bytes),
+                Err(e) => self.search_results = format!("Cannot read {name}: {e}"),
+            }
+        }
+
+        self.calibration_window(ctx);
+
         egui::TopBottomPanel::top("top_panel").show(ctx, |ui| {
             // The top panel is often a good place for a menu bar:
 
@@ -105,6 +675,13 @@ impl eframe::App for TemplateApp {
                     ui.add_space(16.0);
                 }
 
+                ui.menu_button("Detector", |ui| self.resolution_menu(ui));
+                ui.add_space(16.0);
+                ui.menu_button("Background", |ui| self.background_menu(ui));
+                ui.add_space(16.0);
+                ui.menu_button("Spectrum", |ui| self.spectrum_menu(ui));
+                ui.add_space(16.0);
+
                 egui::widgets::global_dark_light_mode_buttons(ui);
             });
         });
@@ -140,20 +717,12 @@ impl eframe::App for TemplateApp {
                             if ui.button("Gamma example").clicked() {
                                 self.user_query = GAMMA_EXAMPLE_STRING.to_string();
                                 self.radiation_type = RadiationType::Gamma;
-                                self.search_results = search_energies(
-                                    self.user_query.clone(),
-                                    &self.radiation_type,
-                                    &self.print_mode,
-                                );
+                                self.search();
                             }
                             if ui.button("Alpha example").clicked() {
                                 self.user_query = ALPHA_EXAMPLE_STRING.to_string();
                                 self.radiation_type = RadiationType::Alpha;
-                                self.search_results = search_energies(
-                                    self.user_query.clone(),
-                                    &self.radiation_type,
-                                    &self.print_mode,
-                                );
+                                self.search();
                             }
                         });
                     });
@@ -161,8 +730,19 @@ impl eframe::App for TemplateApp {
                     strip.cell(|ui| {
                         egui::ScrollArea::vertical().show(ui, |ui| {
                             ui.centered_and_justified(|ui| {
-                                let _user_query_response =
-                                    ui.text_edit_multiline(&mut self.user_query);
+                                let diagnostics = &self.diagnostics;
+                                let mut layouter = |ui: &egui::Ui, text: &str, wrap_width: f32| {
+                                    let mut job = query_layout_job(ui, text, diagnostics);
+                                    job.wrap.max_width = wrap_width;
+                                    ui.fonts(|f| f.layout_job(job))
+                                };
+                                let user_query_response = ui.add(
+                                    egui::TextEdit::multiline(&mut self.user_query)
+                                        .layouter(&mut layouter),
+                                );
+                                if user_query_response.changed() {
+                                    self.diagnostics.clear();
+                                }
                             })
                         });
                     });
@@ -170,7 +750,10 @@ impl eframe::App for TemplateApp {
                     strip.cell(|ui| {
                         egui::ScrollArea::horizontal().show(ui, |ui| {
                             ui.with_layout(egui::Layout::left_to_right(egui::Align::Center), |ui| {
-                                ui.label("Type: ");
+                                ui.label("Type: ").on_hover_text(
+                                    "Type of the lines without their own, \
+                                     e.g. alpha 5.304 MeV 0.5% or gamma 803 keV 1%",
+                                );
                                 ui.radio_value(
                                     &mut self.radiation_type,
                                     RadiationType::Gamma,
@@ -181,6 +764,28 @@ impl eframe::App for TemplateApp {
                                     RadiationType::Alpha,
                                     "Alpha",
                                 );
+                                ui.radio_value(
+                                    &mut self.radiation_type,
+                                    RadiationType::Beta,
+                                    "Beta",
+                                )
+                                .on_hover_text("Matched by the endpoint energy");
+                                ui.radio_value(
+                                    &mut self.radiation_type,
+                                    RadiationType::XRay,
+                                    "X-ray",
+                                );
+                                ui.radio_value(
+                                    &mut self.radiation_type,
+                                    RadiationType::ConversionElectron,
+                                    "CE",
+                                )
+                                .on_hover_text("Conversion electrons");
+                                ui.radio_value(
+                                    &mut self.radiation_type,
+                                    RadiationType::Auger,
+                                    "Auger",
+                                );
                                 ui.horizontal(|ui| ui.separator());
                                 ui.label("Show: ");
                                 ui.radio_value(
@@ -194,19 +799,101 @@ impl eframe::App for TemplateApp {
                                     "everything",
                                 );
                                 ui.horizontal(|ui| ui.separator());
+                                ui.checkbox(
+                                    &mut self.search_options.require_all_lines,
+                                    "all lines must match",
+                                );
+                                ui.checkbox(
+                                    &mut self.search_options.group_chains,
+                                    "group decay chains",
+                                )
+                                .on_hover_text(
+                                    "Rank each parent together with its descendants in \
+                                     secular equilibrium, e.g. 226Ra with 214Pb and 214Bi",
+                                );
+                                ui.label("Max. results: ");
+                                ui.add(
+                                    egui::DragValue::new(&mut self.search_options.max_candidates)
+                                        .clamp_range(1..=500),
+                                );
+                                ui.label("Min. T1/2: ").on_hover_text(
+                                    "Hide the decays of parents living less than this, \
+                                     e.g. 1 h; a query line such as T1/2 > 1 h does the same",
+                                );
+                                ui.add(
+                                    egui::TextEdit::singleline(&mut self.min_half_life)
+                                        .hint_text("e.g. 1 h")
+                                        .desired_width(60.0),
+                                );
+                                ui.label("Cooling: ").on_hover_text(
+                                    "Time since the end of the irradiation, e.g. 7 d: \
+                                     parents that would have decayed away are ranked down, \
+                                     unless they grow in from a longer-lived precursor",
+                                );
+                                ui.add(
+                                    egui::TextEdit::singleline(&mut self.cooling_time)
+                                        .hint_text("e.g. 7 d")
+                                        .desired_width(60.0),
+                                );
+                                ui.label("Sort by: ");
+                                egui::ComboBox::from_id_source("candidate_order")
+                                    .selected_text(self.search_options.order.to_string())
+                                    .show_ui(ui, |ui| {
+                                        for order in CandidateOrder::ALL {
+                                            ui.selectable_value(
+                                                &mut self.search_options.order,
+                                                order,
+                                                order.to_string(),
+                                            );
+                                        }
+                                    });
+                                ui.horizontal(|ui| ui.separator());
                                 let search_response = ui.button("Search");
                                 if search_response.clicked() {
-                                    self.search_results = search_energies(
-                                        self.user_query.clone(),
-                                        &self.radiation_type,
-                                        &self.print_mode,
-                                    );
+                                    self.search();
                                 }
                             })
                         });
                     });
                     // Results area
                     strip.cell(|ui| {
+                        ui.horizontal(|ui| {
+                            if let Some((name, _)) = &self.spectrum {
+                                ui.checkbox(&mut self.plot_spectrum, format!("Plot {name}"));
+                            }
+                            if self.results.is_some() {
+                                ui.checkbox(&mut self.plot_lines, "Plot the lines of a decay");
+                            }
+                        });
+                        let plot_spectrum = self.plot_spectrum && self.spectrum.is_some();
+                        let plot_lines = self.plot_lines && self.results.is_some();
+                        // The plots and the results share the height equally
+                        let plots = plot_spectrum as u8 + plot_lines as u8;
+                        let plot_height = ui.available_height() / (plots + 1) as f32;
+                        if let Some((_, spectrum)) =
+                            self.spectrum.as_ref().filter(|_| plot_spectrum)
+                        {
+                            let calibration = self.calibration.for_spectrum(&spectrum.calibration);
+                            self.spectrum_plot.show(
+                                ui,
+                                plot_height,
+                                spectrum,
+                                &calibration,
+                                &self.query_energies,
+                                self.results.as_ref(),
+                            );
+                        }
+                        if let Some(results) = self.results.as_ref().filter(|_| plot_lines) {
+                            self.line_plot
+                                .show(ui, plot_height, &self.query_energies, results);
+                        }
+                        if let Some(results) = &self.results {
+                            ui.label(&self.search_results);
+                            egui::ScrollArea::horizontal().show(ui, |ui| {
+                                self.results_table.show(ui, results, &self.print_mode);
+                            });
+                            return;
+                        }
                         egui::ScrollArea::vertical().show(ui, |ui| {
                             ui.centered_and_justified(|ui| {
                                 //let result_response =
@@ -0,0 +1,115 @@
+//! Reader for lists of X-rays, conversion electrons and Auger electrons, which ENSDF decay
+//! datasets do not give line by line.
+//!
+//! A list is a CSV file with a header and one radiation per row, as in the decay radiation
+//! tables of NuDat:
+//!
+//! ```text
+//! dataset,type,line,energy,uncertainty,intensity
+//! 137CS B- DECAY (30.08 Y),xray,Kα1,32.194,0.001,3.64
+//! 137CS B- DECAY (30.08 Y),ce,K,624.216,0.003,7.66
+//! ```
+//!
+//! `dataset` is the ENSDF dataset ID of the decay and `type` one of xray, ce or auger;
+//! energies are in keV and intensities per 100 decays of the parent.
+
+use crate::app::RadiationType;
+use crate::csv_rows::csv_rows;
+use crate::database::{EnergyQualifier, Transition};
+use crate::nuclide::Decay;
+use log::warn;
+use serde::Deserialize;
+use std::collections::HashMap;
+
+#[derive(Deserialize)]
+struct Row {
+    dataset: String,
+    #[serde(rename = "type")]
+    radiation_type: String,
+    line: String,
+    energy: f64,
+    #[serde(default)]
+    uncertainty: Option<f64>,
+    intensity: f64,
+}
+
+/// Reads a list of atomic radiations and conversion electrons for the decays in `decays`,
+/// indexed by dataset ID. Rows of other decays are skipped.
+pub fn read_atomic_radiations(
+    input: &str,
+    decays: &HashMap<String, Decay>,
+) -> Result<Vec<Transition>, String> {
+    let mut transitions = Vec::new();
+    for row in csv_rows::<Row>(input) {
+        let (line, row) = row?;
+        let radiation_type = match row.radiation_type.parse() {
+            Ok(
+                t
+                @ (RadiationType::XRay | RadiationType::ConversionElectron | RadiationType::Auger),
+            ) => t,
+            _ => {
+                return Err(format!(
+                    "line {line}: unexpected radiation type {:?}, expected xray, ce or auger",
+                    row.radiation_type
+                ))
+            }
+        };
+        let Some(decay) = decays.get(&row.dataset) else {
+            warn!("line {line}: unknown decay dataset {:?}", row.dataset);
+            continue;
+        };
+        let de = row.uncertainty.unwrap_or(0.0);
+        transitions.push(Transition {
+            decay: decay.clone(),
+            radiation_type,
+            energy: row.energy,
+            uncertainty: de,
+            qualifier: EnergyQualifier::Measured,
+            intensity: row.intensity,
+            lteb: row.energy - de,
+            uteb: row.energy + de,
+            mean_energy: None,
+            line: row.line,
+        });
+    }
+    Ok(transitions)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{assert_close, decay};
+
+    const HEADER: &str = "dataset,type,line,energy,uncertainty,intensity\n";
+
+    fn decays() -> HashMap<String, Decay> {
+        HashMap::from([("A".to_string(), decay("A", 55, 137))])
+    }
+
+    #[test]
+    fn rows_of_known_decays_are_read() {
+        let input = format!("{HEADER}A, xray, Kα1, 32.194, 0.001, 3.64\nB,ce,K,624.216,,7.66\n");
+        let transitions = read_atomic_radiations(&input, &decays()).unwrap();
+        assert_eq!(transitions.len(), 1);
+        let t = &transitions[0];
+        assert_eq!(t.radiation_type, RadiationType::XRay);
+        assert_eq!(t.line, "Kα1");
+        assert_close(t.lteb, 32.193);
+        assert_close(t.uteb, 32.195);
+    }
+
+    #[test]
+    fn unknown_radiation_types_are_rejected() {
+        let input = format!("{HEADER}A,xray,Kα1,32.194,0.001,3.64\nA,gamma,,661.657,,85.1\n");
+        assert_eq!(
+            read_atomic_radiations(&input, &decays()).err(),
+            Some(
+                "line 3: unexpected radiation type \"gamma\", expected xray, ce or auger"
+                    .to_string()
+            )
+        );
+        let input = format!("{HEADER}A,xray,Kα1,lots,0.001,3.64\n");
+        let error = read_atomic_radiations(&input, &decays()).unwrap_err();
+        assert!(error.starts_with("line 2: "), "{error}");
+    }
+}
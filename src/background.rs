@@ -0,0 +1,131 @@
+//! Library of the peaks found in almost every gamma spectrum (natural radioactivity,
+//! annihilation, lead shielding), used to flag the query lines they explain.
+//!
+//! The library can be edited in the app or replaced with a CSV file with a header and one
+//! line per row:
+//!
+//! ```text
+//! name,energy,tolerance
+//! K-40,1460.820,0.5
+//! annihilation,511.0,2.0
+//! ```
+//!
+//! Energies and tolerances are in keV.
+
+use crate::app::RadiationType;
+use crate::csv_rows::csv_rows;
+use crate::query_parser::Energy;
+use serde::{Deserialize, Serialize};
+
+#[derive(Clone, PartialEq, Deserialize, Serialize)]
+pub struct BackgroundLine {
+    /// Source of the line shown to the user, e.g. "K-40" or "Pb Kα1".
+    pub name: String,
+    /// Energy in keV of the photon peak.
+    pub energy: f64,
+    /// Distance in keV from `energy` within which a query line is taken as this peak.
+    #[serde(default)]
+    pub tolerance: f64,
+    #[serde(default = "enabled")]
+    pub enabled: bool,
+}
+
+fn enabled() -> bool {
+    true
+}
+
+impl BackgroundLine {
+    fn new(name: &str, energy: f64, tolerance: f64) -> Self {
+        Self {
+            name: name.to_string(),
+            energy,
+            tolerance,
+            enabled: true,
+        }
+    }
+
+    /// Whether the photon query line `e` overlaps this peak.
+    pub fn explains(&self, e: &Energy) -> bool {
+        let photon = matches!(
+            e.radiation_type,
+            None | Some(RadiationType::Gamma | RadiationType::XRay)
+        );
+        self.enabled
+            && photon
+            && self.energy - self.tolerance <= e.uteb
+            && e.lteb <= self.energy + self.tolerance
+    }
+}
+
+/// Built-in library: 40K, annihilation, lead X-rays excited in the shielding and the strongest
+/// lines of the radon (238U) and thoron (232Th) progeny.
+pub fn default_background() -> Vec<BackgroundLine> {
+    vec![
+        BackgroundLine::new("Pb Kα2", 72.805, 0.5),
+        BackgroundLine::new("Pb Kα1", 74.969, 0.5),
+        BackgroundLine::new("Pb Kβ1", 84.938, 0.5),
+        BackgroundLine::new("Pb Kβ2", 87.300, 0.5),
+        BackgroundLine::new("Pb-212", 238.632, 0.5),
+        BackgroundLine::new("Pb-214", 295.224, 0.5),
+        BackgroundLine::new("Pb-214", 351.932, 0.5),
+        // Doppler broadened, so wider than the other peaks
+        BackgroundLine::new("annihilation", 510.999, 2.0),
+        BackgroundLine::new("Tl-208", 583.187, 0.5),
+        BackgroundLine::new("Bi-214", 609.312, 0.5),
+        BackgroundLine::new("Ac-228", 911.204, 0.5),
+        BackgroundLine::new("Bi-214", 1120.287, 0.5),
+        BackgroundLine::new("K-40", 1460.820, 0.5),
+        BackgroundLine::new("Bi-214", 1764.494, 0.5),
+        BackgroundLine::new("Bi-214", 2204.21, 0.5),
+        BackgroundLine::new("Tl-208", 2614.511, 0.5),
+    ]
+}
+
+/// Reads a background library from a CSV file, see the module documentation.
+pub fn read_background(input: &str) -> Result<Vec<BackgroundLine>, String> {
+    csv_rows(input).map(|row| row.map(|(_, b)| b)).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn window(lteb: f64, uteb: f64, radiation_type: Option<RadiationType>) -> Energy {
+        Energy {
+            radiation_type,
+            ..Energy::new(lteb, uteb, crate::query_parser::Modifier::Definitely)
+        }
+    }
+
+    #[test]
+    fn explains_within_the_tolerance() {
+        let k40 = BackgroundLine::new("K-40", 1460.75, 0.5);
+        // Windows touching the tolerance on either side still overlap it
+        assert!(k40.explains(&window(1461.25, 1462.0, None)));
+        assert!(k40.explains(&window(1459.0, 1460.25, Some(RadiationType::Gamma))));
+        assert!(!k40.explains(&window(1461.375, 1462.0, None)));
+        assert!(!k40.explains(&window(1459.0, 1460.125, None)));
+        // Only photons, and only while enabled
+        assert!(!k40.explains(&window(1460.0, 1461.0, Some(RadiationType::Alpha))));
+        let disabled = BackgroundLine {
+            enabled: false,
+            ..k40
+        };
+        assert!(!disabled.explains(&window(1460.0, 1461.0, None)));
+    }
+
+    #[test]
+    fn read_library() {
+        let input = "name,energy,tolerance\nK-40, 1460.82, 0.5\nPb Kα1,74.969,0.25\n";
+        let lines = read_background(input).unwrap();
+        assert_eq!(lines.len(), 2);
+        assert_eq!(lines[1].name, "Pb Kα1");
+        assert_eq!(lines[1].tolerance, 0.25);
+        assert!(lines[1].enabled);
+        // The tolerance column may be left out
+        let lines = read_background("name,energy\nK-40,1460.82\n").unwrap();
+        assert_eq!(lines[0].tolerance, 0.0);
+        let error = read_background("name,energy\nK-40,high\n").err().unwrap();
+        assert!(error.starts_with("line 2: "), "{error}");
+    }
+}
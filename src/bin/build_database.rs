@@ -0,0 +1,130 @@
+#![warn(clippy::all, rust_2018_idioms)]
+
+// Generates `assets/database.bin` from the files of an ENSDF archival, e.g.:
+//
+//   build_database --output assets/database.bin ensdf_240402/ensdf.*
+//
+// X-rays, conversion electrons and Auger electrons are not given line by line in ENSDF, they
+// are read from optional CSV lists (see `read_atomic_radiations`):
+//
+//   build_database --radiations atomic.csv ensdf_240402/ensdf.*
+
+// When compiling natively:
+#[cfg(not(target_arch = "wasm32"))]
+fn main() -> std::process::ExitCode {
+    native::main()
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+mod native {
+    use decay_radiation_search::{
+        encode_database, read_atomic_radiations, read_transitions, EnsdfSummary,
+    };
+    use log::info;
+    use std::collections::HashMap;
+    use std::process::ExitCode;
+
+    const USAGE: &str = "Usage: build_database [OPTIONS] ENSDF_FILE...
+
+Reads the decay datasets of the given ENSDF files and writes the transition
+database embedded by the app.
+
+Options:
+  -o, --output <FILE>      Database to write (default: assets/database.bin)
+  -r, --radiations <FILE>  CSV list of X-rays, conversion and Auger electrons
+                           (columns: dataset,type,line,energy,uncertainty,intensity)
+  -h, --help               Print this help";
+
+    pub fn main() -> ExitCode {
+        env_logger::Builder::from_env(env_logger::Env::default().default_filter_or("info")).init();
+
+        let mut output = "assets/database.bin".to_string();
+        let mut inputs = Vec::new();
+        let mut radiation_lists = Vec::new();
+        let mut args = std::env::args().skip(1);
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "-o" | "--output" => match args.next() {
+                    Some(o) => output = o,
+                    None => {
+                        eprintln!("build_database: missing value for --output\n\n{USAGE}");
+                        return ExitCode::from(2);
+                    }
+                },
+                "-r" | "--radiations" => match args.next() {
+                    Some(r) => radiation_lists.push(r),
+                    None => {
+                        eprintln!("build_database: missing value for --radiations\n\n{USAGE}");
+                        return ExitCode::from(2);
+                    }
+                },
+                "-h" | "--help" => {
+                    println!("{USAGE}");
+                    return ExitCode::SUCCESS;
+                }
+                _ => inputs.push(arg),
+            }
+        }
+        if inputs.is_empty() {
+            eprintln!("build_database: no ENSDF files given\n\n{USAGE}");
+            return ExitCode::from(2);
+        }
+
+        let mut summary = EnsdfSummary::default();
+        let mut transitions = Vec::new();
+        for path in &inputs {
+            let contents = match std::fs::read(path) {
+                // Some archival files are not valid UTF-8 in their comments
+                Ok(bytes) => String::from_utf8_lossy(&bytes).into_owned(),
+                Err(e) => {
+                    eprintln!("build_database: cannot read {path}: {e}");
+                    return ExitCode::FAILURE;
+                }
+            };
+            let before = transitions.len();
+            transitions.extend(read_transitions(&contents, &mut summary));
+            info!("{path}: {} transitions", transitions.len() - before);
+        }
+
+        let decays: HashMap<_, _> = transitions
+            .iter()
+            .map(|t| (t.decay.dataset.clone(), t.decay.clone()))
+            .collect();
+        for path in &radiation_lists {
+            let read = std::fs::read_to_string(path)
+                .map_err(|e| e.to_string())
+                .and_then(|contents| read_atomic_radiations(&contents, &decays));
+            match read {
+                Ok(list) => {
+                    info!(
+                        "{path}: {} atomic radiations and conversion electrons",
+                        list.len()
+                    );
+                    transitions.extend(list);
+                }
+                Err(e) => {
+                    eprintln!("build_database: cannot read {path}: {e}");
+                    return ExitCode::FAILURE;
+                }
+            }
+        }
+
+        let bytes = match encode_database(&transitions) {
+            Ok(b) => b,
+            Err(e) => {
+                eprintln!("build_database: cannot encode the database: {e}");
+                return ExitCode::FAILURE;
+            }
+        };
+        if let Err(e) = std::fs::write(&output, bytes) {
+            eprintln!("build_database: cannot write {output}: {e}");
+            return ExitCode::FAILURE;
+        }
+        println!("{output}: {summary}");
+        ExitCode::SUCCESS
+    }
+}
+
+// The database generator is only meaningful natively:
+#[cfg(target_arch = "wasm32")]
+fn main() {}
@@ -0,0 +1,334 @@
+#![warn(clippy::all, rust_2018_idioms)]
+
+// Headless command-line front end for the same search used by the app, e.g.:
+//
+//   drs search --type gamma query.txt
+//   cat query.txt | drs search --type alpha --show everything
+
+// When compiling natively:
+#[cfg(not(target_arch = "wasm32"))]
+fn main() -> std::process::ExitCode {
+    native::main()
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+mod native {
+    use decay_radiation_search::{
+        find_peaks, format_results, parse_duration, parse_query, read_background,
+        read_calibration_points, read_spectrum, EnergyCalibration, HalfLifeFilter, OutputFormat,
+        Peak, PeakSearch, PrintMode, Query, RadiationType, ResolutionModel, SearchOptions,
+    };
+
+    const USAGE: &str = "Usage: drs search [OPTIONS] [FILE]
+
+Reads a search query from FILE (or stdin when FILE is omitted or `-`)
+and prints the matching decays to stdout.
+
+With --spectrum, the query lines are instead the peaks found in a spectrum
+(.Spe, .Chn, .n42 or two-column .csv); they are listed on stderr.
+
+Options:
+  -t, --type <TYPE>                Radiation type of the lines without a type prefix:
+                                   gamma, alpha, beta, xray, ce or auger (default: gamma)
+  -s, --show <matches|everything>  Records shown for each decay (default: matches)
+  -f, --format <text|json|csv>     Output format (default: text)
+  -n, --top <N>                    Number of best ranked decays shown (default: 20)
+      --sort <score|a|z|name|half-life>
+                                   Order of the decays shown: by score, parent mass number,
+                                   Z, element name or half-life (default: score)
+      --min-half-life <DURATION>   Only show decays of parents living at least DURATION,
+                                   e.g. 1h, 30min or 2y (also written T1/2 > 1 h in the query)
+      --max-half-life <DURATION>   Only show decays of parents living at most DURATION
+      --cooling-time <DURATION>    Time since the end of the irradiation: ranks down the
+                                   parents that decayed away, unless fed by a precursor
+  -a, --all-lines                  Only show decays explaining every line of the query
+  -b, --background                 Flag the lines of the built-in background library
+                                   (40K, 511 keV, Pb X-rays, radon and thoron progeny);
+                                   they need not be explained by the decays found
+      --background-lines <FILE>    Same, with the library read from a CSV file with the
+                                   columns name, energy and tolerance (keV)
+  -c, --chains                     Group each parent with its descendants in secular
+                                   equilibrium, e.g. 226Ra with 214Pb and 214Bi
+      --hpge <A,B,C>               Match lines without uncertainty within the resolution
+                                   of an HPGe detector, FWHM = sqrt(A + B*E + C*E^2) keV
+      --scintillator <PERCENT>     Same, for a scintillator with the given FWHM at 662 keV
+      --spectrum <FILE>            Search the peaks found in a spectrum file
+      --peak-fwhm <CHANNELS>       Expected FWHM of the peaks, in channels (default: 5)
+      --peak-threshold <SIGMAS>    Smallest significance of the peaks (default: 4)
+      --peak-window <SIGMAS>       Match window of the fitted peaks, in standard
+                                   uncertainties of their energy (default: 3)
+      --calibration <FILE>         Energy calibration of the spectrum, fitted to the peaks
+                                   of known energy of a CSV file with the columns channel
+                                   and energy (keV); replaces the calibration of the file
+      --calibration-degree <1|2>   Linear or quadratic calibration (default: 1)
+  -h, --help                       Print this help";
+
+    struct SearchArgs {
+        radiation_type: RadiationType,
+        print_mode: PrintMode,
+        output_format: OutputFormat,
+        options: SearchOptions,
+        resolution: ResolutionModel,
+        input: Option<String>,
+        spectrum: Option<String>,
+        peak_search: PeakSearch,
+        calibration: EnergyCalibration,
+    }
+
+    /// Returns `Ok(None)` when help was requested.
+    fn parse_args(args: &[String]) -> Result<Option<SearchArgs>, String> {
+        let mut args = args.iter();
+        match args.next().map(String::as_str) {
+            Some("search") => {}
+            Some("-h") | Some("--help") => return Ok(None),
+            Some(other) => return Err(format!("unknown command: {other}")),
+            None => return Err("missing command".to_string()),
+        }
+
+        let mut search_args = SearchArgs {
+            radiation_type: RadiationType::Gamma,
+            print_mode: PrintMode::OnlyMatches,
+            output_format: OutputFormat::Text,
+            options: SearchOptions::default(),
+            resolution: ResolutionModel::None,
+            input: None,
+            spectrum: None,
+            peak_search: PeakSearch::default(),
+            calibration: EnergyCalibration {
+                replace_file_calibration: true,
+                ..EnergyCalibration::default()
+            },
+        };
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "-t" | "--type" => {
+                    let value = args.next().ok_or("missing value for --type")?;
+                    search_args.radiation_type = value.parse()?;
+                }
+                "-s" | "--show" => {
+                    search_args.print_mode = match args.next().map(String::as_str) {
+                        Some("matches") => PrintMode::OnlyMatches,
+                        Some("everything") => PrintMode::Everything,
+                        Some(other) => return Err(format!("unknown value for --show: {other}")),
+                        None => return Err("missing value for --show".to_string()),
+                    };
+                }
+                "-f" | "--format" => {
+                    let value = args.next().ok_or("missing value for --format")?;
+                    search_args.output_format = value.parse()?;
+                }
+                "-n" | "--top" => {
+                    let value = args.next().ok_or("missing value for --top")?;
+                    search_args.options.max_candidates = value
+                        .parse()
+                        .map_err(|_| format!("invalid value for --top: {value}"))?;
+                }
+                "--sort" => {
+                    let value = args.next().ok_or("missing value for --sort")?;
+                    search_args.options.order = value.parse()?;
+                }
+                "--min-half-life" => {
+                    let value = args.next().ok_or("missing value for --min-half-life")?;
+                    search_args.options.half_life.min = Some(parse_duration(value)?);
+                }
+                "--max-half-life" => {
+                    let value = args.next().ok_or("missing value for --max-half-life")?;
+                    search_args.options.half_life.max = Some(parse_duration(value)?);
+                }
+                "--cooling-time" => {
+                    let value = args.next().ok_or("missing value for --cooling-time")?;
+                    search_args.options.cooling_time = Some(parse_duration(value)?);
+                }
+                "-a" | "--all-lines" => search_args.options.require_all_lines = true,
+                "-b" | "--background" => search_args.options.flag_background = true,
+                "--background-lines" => {
+                    let path = args.next().ok_or("missing value for --background-lines")?;
+                    let contents = std::fs::read_to_string(path)
+                        .map_err(|e| format!("cannot read {path}: {e}"))?;
+                    search_args.options.background =
+                        read_background(&contents).map_err(|e| format!("{path}: {e}"))?;
+                    search_args.options.flag_background = true;
+                }
+                "-c" | "--chains" => search_args.options.group_chains = true,
+                "--hpge" => {
+                    let value = args.next().ok_or("missing value for --hpge")?;
+                    let coefficients = value
+                        .split(',')
+                        .map(|c| c.trim().parse::<f64>())
+                        .collect::<Result<Vec<f64>, _>>()
+                        .map_err(|_| format!("invalid value for --hpge: {value}"))?;
+                    let [a, b, c] = coefficients[..] else {
+                        return Err(format!("--hpge expects three coefficients: {value}"));
+                    };
+                    search_args.resolution = ResolutionModel::Hpge { a, b, c };
+                }
+                "--scintillator" => {
+                    let value = args.next().ok_or("missing value for --scintillator")?;
+                    search_args.resolution = ResolutionModel::Scintillator {
+                        percent_at_662: value
+                            .trim_end_matches('%')
+                            .parse()
+                            .map_err(|_| format!("invalid value for --scintillator: {value}"))?,
+                    };
+                }
+                "--spectrum" => {
+                    let value = args.next().ok_or("missing value for --spectrum")?;
+                    search_args.spectrum = Some(value.clone());
+                }
+                "--peak-fwhm" => {
+                    let value = args.next().ok_or("missing value for --peak-fwhm")?;
+                    search_args.peak_search.fwhm = value
+                        .parse()
+                        .map_err(|_| format!("invalid value for --peak-fwhm: {value}"))?;
+                    search_args.peak_search.check()?;
+                }
+                "--peak-threshold" => {
+                    let value = args.next().ok_or("missing value for --peak-threshold")?;
+                    search_args.peak_search.threshold = value
+                        .parse()
+                        .map_err(|_| format!("invalid value for --peak-threshold: {value}"))?;
+                }
+                "--peak-window" => {
+                    let value = args.next().ok_or("missing value for --peak-window")?;
+                    search_args.peak_search.sigmas = value
+                        .parse()
+                        .map_err(|_| format!("invalid value for --peak-window: {value}"))?;
+                }
+                "--calibration" => {
+                    let path = args.next().ok_or("missing value for --calibration")?;
+                    let contents = std::fs::read_to_string(path)
+                        .map_err(|e| format!("cannot read {path}: {e}"))?;
+                    search_args.calibration.points =
+                        read_calibration_points(&contents).map_err(|e| format!("{path}: {e}"))?;
+                }
+                "--calibration-degree" => {
+                    search_args.calibration.degree = match args.next().map(String::as_str) {
+                        Some("1") => 1,
+                        Some("2") => 2,
+                        Some(other) => {
+                            return Err(format!("invalid value for --calibration-degree: {other}"))
+                        }
+                        None => return Err("missing value for --calibration-degree".to_string()),
+                    };
+                }
+                "-h" | "--help" => return Ok(None),
+                "-" => search_args.input = None,
+                _ if arg.starts_with('-') => return Err(format!("unknown option: {arg}")),
+                _ => search_args.input = Some(arg.clone()),
+            }
+        }
+        Ok(Some(search_args))
+    }
+
+    /// Query made of the peaks found in the spectrum file at `path`, listed on stderr.
+    fn spectrum_query(
+        path: &str,
+        settings: &PeakSearch,
+        calibration: &EnergyCalibration,
+    ) -> Result<Query, String> {
+        let bytes = std::fs::read(path).map_err(|e| format!("cannot read {path}: {e}"))?;
+        let mut spectrum = read_spectrum(path, &bytes).map_err(|e| format!("{path}: {e}"))?;
+        if !calibration.points.is_empty() {
+            if calibration.coefficients.is_empty() {
+                return Err(format!(
+                    "not enough calibration points for a polynomial of degree {}",
+                    calibration.degree
+                ));
+            }
+            eprintln!("drs: energy calibration {calibration}");
+            for (point, residual) in calibration.points.iter().zip(calibration.residuals()) {
+                eprintln!(
+                    "       channel {:.2}  {:.3} keV  residual {:+.3} keV",
+                    point.channel,
+                    point.energy,
+                    residual.unwrap_or(f64::NAN)
+                );
+            }
+            calibration.apply(&mut spectrum);
+        }
+        if spectrum.calibration.is_empty() {
+            return Err(format!(
+                "{path}: the spectrum has no energy calibration, give one with --calibration"
+            ));
+        }
+        let peaks = find_peaks(&spectrum, settings);
+        eprintln!("drs: {} peaks found in {path}:", peaks.len());
+        for (i, line) in peaks.iter().filter_map(Peak::query_line).enumerate() {
+            eprintln!("{:>5}  {line}", i + 1);
+        }
+        Ok(Query {
+            energies: peaks.iter().filter_map(Peak::to_energy).collect(),
+            half_life: HalfLifeFilter::default(),
+        })
+    }
+
+    fn read_query(input: &Option<String>) -> std::io::Result<String> {
+        use std::io::Read;
+        match input {
+            Some(path) => std::fs::read_to_string(path),
+            None => {
+                let mut query = String::new();
+                std::io::stdin().read_to_string(&mut query)?;
+                Ok(query)
+            }
+        }
+    }
+
+    pub fn main() -> std::process::ExitCode {
+        use std::process::ExitCode;
+        env_logger::init(); // Log to stderr (if you run with `RUST_LOG=debug`).
+
+        let args: Vec<String> = std::env::args().skip(1).collect();
+        let search_args = match parse_args(&args) {
+            Ok(Some(a)) => a,
+            Ok(None) => {
+                println!("{USAGE}");
+                return ExitCode::SUCCESS;
+            }
+            Err(e) => {
+                eprintln!("drs: {e}\n\n{USAGE}");
+                return ExitCode::from(2);
+            }
+        };
+
+        let mut calibration = search_args.calibration;
+        calibration.fit();
+        let query = match &search_args.spectrum {
+            Some(path) => spectrum_query(path, &search_args.peak_search, &calibration),
+            None => match read_query(&search_args.input) {
+                Ok(q) => parse_query(&q, &search_args.resolution).map_err(|e| e.to_string()),
+                Err(e) => Err(format!("cannot read query: {e}")),
+            },
+        };
+        let query = match query {
+            Ok(q) => q,
+            Err(e) => {
+                eprintln!("drs: {e}");
+                return ExitCode::FAILURE;
+            }
+        };
+
+        let results = match query.search(&search_args.radiation_type, &search_args.options) {
+            Ok(r) => r,
+            Err(e) => {
+                eprintln!("drs: {e}");
+                return ExitCode::FAILURE;
+            }
+        };
+
+        match format_results(results, &search_args.print_mode, &search_args.output_format) {
+            Ok(output) => {
+                println!("{output}");
+                ExitCode::SUCCESS
+            }
+            Err(e) => {
+                eprintln!("drs: cannot write the results: {e}");
+                ExitCode::FAILURE
+            }
+        }
+    }
+}
+
+// The command-line tool is only meaningful natively:
+#[cfg(target_arch = "wasm32")]
+fn main() {}
@@ -0,0 +1,224 @@
+//! Channel to energy calibration fitted to peaks of known energy, for spectra saved without
+//! one (or with a wrong one).
+//!
+//! The points can also be read from a CSV file with a header and one peak per row:
+//!
+//! ```text
+//! channel,energy
+//! 2645.3,661.657
+//! 4691.8,1173.228
+//! 5328.7,1332.492
+//! ```
+
+use crate::csv_rows::csv_rows;
+use crate::fit::{fit_polynomial, polynomial};
+use crate::spectrum::Spectrum;
+use serde::{Deserialize, Serialize};
+
+/// Lines of the usual check sources, offered when assigning peaks.
+pub const REFERENCE_LINES: [(&str, f64); 9] = [
+    ("Am-241", 59.5409),
+    ("Cd-109", 88.0336),
+    ("Co-57", 122.06065),
+    ("Ba-133", 356.0129),
+    ("Cs-137", 661.657),
+    ("Co-60", 1173.228),
+    ("Co-60", 1332.492),
+    ("K-40", 1460.820),
+    ("Tl-208", 2614.511),
+];
+
+/// A peak assigned to a line of known energy.
+#[derive(Clone, PartialEq, Deserialize, Serialize)]
+pub struct CalibrationPoint {
+    pub channel: f64,
+    /// Energy of the line in keV.
+    pub energy: f64,
+}
+
+#[derive(Clone, PartialEq, Deserialize, Serialize)]
+#[serde(default)]
+pub struct EnergyCalibration {
+    pub points: Vec<CalibrationPoint>,
+    /// Degree of the polynomial, 1 (linear) or 2 (quadratic).
+    pub degree: usize,
+    /// Fitted coefficients, E = c[0] + c[1]·ch + c[2]·ch² in keV; empty when there are not
+    /// enough points.
+    pub coefficients: Vec<f64>,
+    /// Also apply the calibration to spectra that carry their own.
+    pub replace_file_calibration: bool,
+}
+
+impl Default for EnergyCalibration {
+    fn default() -> Self {
+        Self {
+            points: vec![],
+            degree: 1,
+            coefficients: vec![],
+            replace_file_calibration: false,
+        }
+    }
+}
+
+impl EnergyCalibration {
+    /// Fits the coefficients to the points, clearing them when there are fewer distinct
+    /// channels than coefficients.
+    pub fn fit(&mut self) {
+        let points: Vec<(f64, f64)> = self.points.iter().map(|p| (p.channel, p.energy)).collect();
+        self.coefficients = fit_polynomial(&points, self.degree).unwrap_or_default();
+    }
+
+    pub fn energy(&self, channel: f64) -> Option<f64> {
+        (!self.coefficients.is_empty()).then(|| polynomial(&self.coefficients, channel))
+    }
+
+    /// Differences in keV between the energy of each point and the calibration at its channel.
+    pub fn residuals(&self) -> Vec<Option<f64>> {
+        self.points
+            .iter()
+            .map(|p| self.energy(p.channel).map(|e| p.energy - e))
+            .collect()
+    }
+
+    /// Root mean square of the residuals, `None` without a fit.
+    pub fn rms_residual(&self) -> Option<f64> {
+        let residuals: Vec<f64> = self.residuals().into_iter().flatten().collect();
+        (!residuals.is_empty())
+            .then(|| (residuals.iter().map(|r| r * r).sum::<f64>() / residuals.len() as f64).sqrt())
+    }
+
+    /// Whether this calibration is used for a spectrum whose file gives `file_calibration`:
+    /// when it is fitted and the file has none or it should be replaced.
+    pub fn replaces(&self, file_calibration: &[f64]) -> bool {
+        !self.coefficients.is_empty()
+            && (file_calibration.is_empty() || self.replace_file_calibration)
+    }
+
+    /// Calibration to use for a spectrum whose file gives `file_calibration`.
+    pub fn for_spectrum(&self, file_calibration: &[f64]) -> Vec<f64> {
+        match self.replaces(file_calibration) {
+            true => self.coefficients.clone(),
+            false => file_calibration.to_vec(),
+        }
+    }
+
+    /// Calibrates `spectrum` with this calibration when it replaces that of the file, the RMS
+    /// residual giving the uncertainty of its energies (and so widening the peak windows).
+    pub fn apply(&self, spectrum: &mut Spectrum) {
+        if self.replaces(&spectrum.calibration) {
+            spectrum.calibration = self.coefficients.clone();
+            spectrum.calibration_uncertainty = self.rms_residual().unwrap_or(0.0);
+        }
+    }
+}
+
+impl std::fmt::Display for EnergyCalibration {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if self.coefficients.is_empty() {
+            return write!(f, "not calibrated");
+        }
+        write!(f, "E = {:.4}", self.coefficients[0])?;
+        for (k, c) in self.coefficients.iter().enumerate().skip(1) {
+            let sign = if *c < 0.0 { '-' } else { '+' };
+            match k {
+                1 => write!(f, " {sign} {:.6}·ch", c.abs())?,
+                _ => write!(f, " {sign} {:.4e}·ch^{k}", c.abs())?,
+            }
+        }
+        write!(f, " keV")
+    }
+}
+
+/// Reads calibration points from a CSV file, see the module documentation.
+pub fn read_calibration_points(input: &str) -> Result<Vec<CalibrationPoint>, String> {
+    csv_rows(input).map(|row| row.map(|(_, p)| p)).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::assert_close;
+
+    fn calibration(points: &[(f64, f64)], degree: usize) -> EnergyCalibration {
+        let mut calibration = EnergyCalibration {
+            points: points
+                .iter()
+                .map(|&(channel, energy)| CalibrationPoint { channel, energy })
+                .collect(),
+            degree,
+            ..Default::default()
+        };
+        calibration.fit();
+        calibration
+    }
+
+    #[test]
+    fn quadratic_through_three_points() {
+        // E = 0.3 + 0.25·ch + 1e-7·ch²
+        let energy = |ch: f64| 0.3 + 0.25 * ch + 1e-7 * ch * ch;
+        let points: Vec<(f64, f64)> = [200.0, 2600.0, 5300.0]
+            .iter()
+            .map(|&ch| (ch, energy(ch)))
+            .collect();
+        let c = calibration(&points, 2);
+        assert_eq!(c.coefficients.len(), 3);
+        assert_close(c.energy(4000.0).unwrap(), energy(4000.0));
+        for r in c.residuals() {
+            assert_close(r.unwrap(), 0.0);
+        }
+        assert!(c.rms_residual().unwrap() < 1e-9);
+    }
+
+    #[test]
+    fn linear_fit_residuals() {
+        let c = calibration(&[(0.0, 0.0), (1.0, 2.0), (2.0, 2.0), (3.0, 4.0)], 1);
+        let residuals: Vec<f64> = c.residuals().into_iter().flatten().collect();
+        for (r, expected) in residuals.iter().zip([-0.2, 0.6, -0.6, 0.2]) {
+            assert_close(*r, expected);
+        }
+        assert_close(c.rms_residual().unwrap(), 0.2f64.sqrt());
+        assert_eq!(c.to_string(), "E = 0.2000 + 1.200000·ch keV");
+    }
+
+    #[test]
+    fn too_few_points() {
+        let c = calibration(&[(100.0, 25.0), (200.0, 50.0)], 2);
+        assert!(c.coefficients.is_empty());
+        assert_eq!(c.energy(10.0), None);
+        assert_eq!(c.rms_residual(), None);
+        assert_eq!(c.to_string(), "not calibrated");
+    }
+
+    #[test]
+    fn apply_to_spectra() {
+        let mut c = calibration(&[(0.0, 0.0), (1.0, 2.0), (2.0, 2.0), (3.0, 4.0)], 1);
+        let mut uncalibrated = Spectrum::default();
+        c.apply(&mut uncalibrated);
+        assert_eq!(uncalibrated.calibration, c.coefficients);
+        assert_eq!(
+            uncalibrated.calibration_uncertainty,
+            c.rms_residual().unwrap()
+        );
+        // The calibration of the file is kept unless it should be replaced
+        let mut calibrated = Spectrum {
+            calibration: vec![0.5, 0.25],
+            ..Default::default()
+        };
+        assert!(!c.replaces(&calibrated.calibration));
+        c.apply(&mut calibrated);
+        assert_eq!(calibrated.calibration, [0.5, 0.25]);
+        assert_eq!(calibrated.calibration_uncertainty, 0.0);
+        c.replace_file_calibration = true;
+        assert_eq!(c.for_spectrum(&calibrated.calibration), c.coefficients);
+    }
+
+    #[test]
+    fn read_points() {
+        let points = read_calibration_points("channel,energy\n2645.3, 661.657\n4691.8,1173.228\n")
+            .unwrap_or_else(|e| panic!("{e}"));
+        let points: Vec<(f64, f64)> = points.iter().map(|p| (p.channel, p.energy)).collect();
+        assert_eq!(points, [(2645.3, 661.657), (4691.8, 1173.228)]);
+        let error = read_calibration_points("channel,energy\n2645.3,661.657\nx,1\n").err();
+        assert!(error.is_some_and(|e| e.starts_with("line 3: ")));
+    }
+}
@@ -0,0 +1,173 @@
+use crate::database::{decays_of, precursors};
+use crate::nuclide::{Decay, HalfLife, Nuclide};
+
+/// A decay of a chain, see [`chain`].
+pub struct ChainMember {
+    pub decay: Decay,
+    /// Activity of the parent of the decay relative to the one of the head of the chain.
+    pub fraction: f64,
+}
+
+fn half_life(decay: &Decay) -> Option<f64> {
+    decay.half_life.as_ref().map(HalfLife::seconds)
+}
+
+/// The decay `head` followed by the decays of its descendants in secular equilibrium with it,
+/// i.e. of shorter half-life, going down the levels of the daughters each decay feeds: the
+/// ground state and the isomers (e.g. 234mPa, fed by almost every 234Th decay).
+///
+/// The activity of each descendant is the product of the branching ratios and level feedings
+/// leading to it. A descendant fed by several branches (e.g. 210Pb from both 214Po and 210Tl)
+/// only counts the first one found, which carries almost all of the activity in the natural
+/// series.
+pub fn chain(head: &Decay) -> Vec<ChainMember> {
+    chain_with(head, decays_of)
+}
+
+/// [`chain`], with the decays of each nuclide given by `decays_of`.
+fn chain_with(head: &Decay, decays_of: impl Fn(&Nuclide) -> Vec<Decay>) -> Vec<ChainMember> {
+    let mut members = vec![ChainMember {
+        decay: head.clone(),
+        fraction: 1.0,
+    }];
+    let Some(limit) = half_life(head) else {
+        return members;
+    };
+    let mut i = 0;
+    while i < members.len() {
+        let decay = &members[i].decay;
+        let fraction = members[i].fraction * decay.branching_ratio.unwrap_or(1.0);
+        for (level, fed) in decay.fed_levels() {
+            for decay in decays_of(&level) {
+                let in_equilibrium = half_life(&decay).is_some_and(|t| t < limit);
+                if in_equilibrium && !members.iter().any(|m| m.decay.dataset == decay.dataset) {
+                    members.push(ChainMember {
+                        decay,
+                        fraction: fraction * fed,
+                    });
+                }
+            }
+        }
+        i += 1;
+    }
+    members
+}
+
+/// Parent of `decay` and its ancestors in the database that it can be in secular equilibrium
+/// with, i.e. the heads of the chains [`chain`] builds that contain `decay`: the ancestors
+/// living longer than every nuclide between them and `decay`.
+pub fn heads(decay: &Decay) -> Vec<Nuclide> {
+    heads_with(decay, precursors)
+}
+
+/// [`heads`], with the decays feeding each nuclide given by `precursors`.
+fn heads_with(decay: &Decay, precursors: impl Fn(&Nuclide) -> Vec<Decay>) -> Vec<Nuclide> {
+    let mut heads = vec![decay.parent.clone()];
+    // Ancestors to go up from, with the longest half-life down to `decay`
+    let mut stack = vec![(
+        decay.parent.clone(),
+        half_life(decay).unwrap_or(f64::INFINITY),
+    )];
+    let mut visited = vec![decay.parent.clone()];
+    while let Some((nuclide, longest)) = stack.pop() {
+        for p in precursors(&nuclide) {
+            // Chains only go down the levels the decays feed
+            let feeds = p.fed_levels().iter().any(|(l, _)| l.same_level(&nuclide));
+            if !feeds || visited.contains(&p.parent) {
+                continue;
+            }
+            visited.push(p.parent.clone());
+            let t = half_life(&p).unwrap_or(f64::INFINITY);
+            if t > longest {
+                heads.push(p.parent.clone());
+            }
+            stack.push((p.parent, t.max(longest)));
+        }
+    }
+    heads
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::nuclide::DecayMode;
+    use crate::testing::{assert_close, decay};
+
+    /// β- decay of (`z`, 226) with the given half-life in seconds and branching ratio.
+    fn beta(dataset: &str, z: u32, seconds: f64, branching_ratio: f64) -> Decay {
+        Decay {
+            half_life: Some(HalfLife {
+                value: seconds,
+                uncertainty: 0.0,
+                unit: "s".to_string(),
+            }),
+            branching_ratio: Some(branching_ratio),
+            ..decay(dataset, z, 226)
+        }
+    }
+
+    /// A head with two decays, each feeding a short-lived daughter, then a long-lived one.
+    fn decays() -> Vec<Decay> {
+        let mut ec = beta("RA EC", 88, 1000.0, 0.4);
+        ec.mode = DecayMode::ElectronCapture;
+        ec.daughter.atomic_number = 87;
+        vec![
+            beta("RA B-", 88, 1000.0, 0.6),
+            ec,
+            beta("AC B-", 89, 10.0, 1.0),
+            beta("FR B-", 87, 1.0, 1.0),
+            beta("TH B-", 90, 5000.0, 1.0),
+        ]
+    }
+
+    fn chain_of(dataset: &str) -> Vec<(String, f64)> {
+        let decays = decays();
+        let head = decays.iter().find(|d| d.dataset == dataset).unwrap();
+        let decays_of = |n: &Nuclide| {
+            let found = decays.iter().filter(|d| d.parent.same_level(n));
+            found.cloned().collect()
+        };
+        let members = chain_with(head, decays_of);
+        members
+            .into_iter()
+            .map(|m| (m.decay.dataset, m.fraction))
+            .collect()
+    }
+
+    fn heads_of(dataset: &str) -> Vec<u32> {
+        let decays = decays();
+        let decay = decays.iter().find(|d| d.dataset == dataset).unwrap();
+        let precursors = |n: &Nuclide| {
+            let found = decays.iter().filter(|d| {
+                (d.daughter.atomic_number, d.daughter.mass_number)
+                    == (n.atomic_number, n.mass_number)
+            });
+            found.cloned().collect()
+        };
+        heads_with(decay, precursors)
+            .into_iter()
+            .map(|n| n.atomic_number)
+            .collect()
+    }
+
+    #[test]
+    fn chains_start_with_one_decay_of_the_head() {
+        let chain = chain_of("RA B-");
+        let datasets: Vec<&str> = chain.iter().map(|(d, _)| d.as_str()).collect();
+        // 226Th lives longer than 226Ra, so is not in equilibrium with it
+        assert_eq!(datasets, ["RA B-", "AC B-"]);
+        assert_close(chain[1].1, 0.6);
+
+        let chain = chain_of("RA EC");
+        let datasets: Vec<&str> = chain.iter().map(|(d, _)| d.as_str()).collect();
+        assert_eq!(datasets, ["RA EC", "FR B-"]);
+        assert_close(chain[1].1, 0.4);
+    }
+
+    #[test]
+    fn heads_are_the_longer_lived_ancestors() {
+        assert_eq!(heads_of("AC B-"), [89, 88]);
+        assert_eq!(heads_of("FR B-"), [87, 88]);
+        assert_eq!(heads_of("TH B-"), [90]);
+    }
+}
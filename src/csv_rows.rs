@@ -0,0 +1,21 @@
+//! Reader for the CSV files with a header used for the lists the user can load.
+
+use serde::de::DeserializeOwned;
+
+/// Rows of a CSV file with a header, fields trimmed, each with the line it was read from.
+/// Errors name the line too.
+pub fn csv_rows<'a, T: DeserializeOwned + 'a>(
+    input: &'a str,
+) -> impl Iterator<Item = Result<(usize, T), String>> + 'a {
+    csv::ReaderBuilder::new()
+        .trim(csv::Trim::All)
+        .from_reader(input.as_bytes())
+        .into_deserialize()
+        .enumerate()
+        .map(|(i, row)| {
+            // Line 1 is the header
+            let line = i + 2;
+            row.map(|row| (line, row))
+                .map_err(|e| format!("line {line}: {e}"))
+        })
+}
@@ -1,32 +1,192 @@
-use crate::{app::RadiationType, query_parser::Energy};
+use crate::nuclide::{Decay, Nuclide};
+use crate::{
+    app::RadiationType,
+    query_parser::{Energy, Modifier},
+};
 use core::fmt;
 use log::debug;
 use once_cell::sync::Lazy;
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 use std::collections::{HashMap, HashSet};
 
 const DATABASE_BYTES: &[u8] = include_bytes!("../assets/database.bin");
 
 //const DATABASE: Vec<Transition> = rmp_serde::from_slice(DATABASE_BYTES).unwrap();
-static DATABASE: Lazy<Vec<Transition>> =
-    Lazy::new(|| rmp_serde::from_slice(DATABASE_BYTES).unwrap());
+static DATABASE: Lazy<Database> =
+    Lazy::new(|| Database::new(rmp_serde::from_slice(DATABASE_BYTES).unwrap()));
 
-#[derive(Debug, Clone, Deserialize)]
+/// Transitions of one radiation type sorted by `lteb`, for binary-search range lookups.
+struct EnergyIndex {
+    sorted: Vec<usize>,
+    /// Widest `uteb - lteb` in the index, bounding how far below a query a match can start.
+    max_width: f64,
+}
+
+struct Database {
+    transitions: Vec<Transition>,
+    by_energy: HashMap<RadiationType, EnergyIndex>,
+    /// Indexes of the transitions of each decay dataset, sorted by energy.
+    by_decay: HashMap<String, Vec<usize>>,
+    /// Datasets of the decays feeding each nuclide, by (Z, A) of their daughter.
+    by_daughter: HashMap<(u32, u32), Vec<String>>,
+    /// Datasets of the decays of each nuclide, by (Z, A) of their parent, sorted.
+    by_parent: HashMap<(u32, u32), Vec<String>>,
+}
+
+impl Database {
+    fn new(transitions: Vec<Transition>) -> Self {
+        let mut by_energy: HashMap<RadiationType, EnergyIndex> = HashMap::new();
+        let mut by_decay: HashMap<String, Vec<usize>> = HashMap::new();
+        for (i, t) in transitions.iter().enumerate() {
+            by_decay.entry(t.decay.dataset.clone()).or_default().push(i);
+            if !t.qualifier.is_matchable() {
+                continue;
+            }
+            let index = by_energy.entry(t.radiation_type).or_insert(EnergyIndex {
+                sorted: vec![],
+                max_width: 0.0,
+            });
+            index.sorted.push(i);
+            index.max_width = index.max_width.max(t.uteb - t.lteb);
+        }
+        for index in by_energy.values_mut() {
+            index
+                .sorted
+                .sort_by(|&a, &b| transitions[a].lteb.total_cmp(&transitions[b].lteb));
+        }
+        let mut by_daughter: HashMap<(u32, u32), Vec<String>> = HashMap::new();
+        let mut by_parent: HashMap<(u32, u32), Vec<String>> = HashMap::new();
+        for (dataset, list) in by_decay.iter_mut() {
+            list.sort_by(|&a, &b| transitions[a].energy.total_cmp(&transitions[b].energy));
+            let decay = &transitions[list[0]].decay;
+            by_daughter
+                .entry((decay.daughter.atomic_number, decay.daughter.mass_number))
+                .or_default()
+                .push(dataset.clone());
+            by_parent
+                .entry((decay.parent.atomic_number, decay.parent.mass_number))
+                .or_default()
+                .push(dataset.clone());
+        }
+        for list in by_parent.values_mut() {
+            list.sort();
+        }
+        Self {
+            transitions,
+            by_energy,
+            by_decay,
+            by_daughter,
+            by_parent,
+        }
+    }
+
+    /// Transitions of the radiation type of `e` whose window overlaps the one of `e`.
+    fn in_range<'a>(&'a self, e: &'a Energy) -> impl Iterator<Item = &'a Transition> + 'a {
+        let index = e.radiation_type.and_then(|t| self.by_energy.get(&t));
+        let (sorted, max_width) = match index {
+            Some(index) => (index.sorted.as_slice(), index.max_width),
+            None => (&[][..], 0.0),
+        };
+        let lteb = |&i: &usize| self.transitions[i].lteb;
+        let start = sorted.partition_point(|i| lteb(i) < e.lteb - max_width);
+        let end = sorted.partition_point(|i| lteb(i) <= e.uteb);
+        sorted[start..end.max(start)]
+            .iter()
+            .map(|&i| &self.transitions[i])
+            .filter(|t| energy_in_transition_range(e, t))
+    }
+
+    fn decay(&self, dataset: &str) -> impl Iterator<Item = &Transition> {
+        self.by_decay
+            .get(dataset)
+            .into_iter()
+            .flatten()
+            .map(|&i| &self.transitions[i])
+    }
+}
+
+/// How an ENSDF energy was given, beyond its value and uncertainty.
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize, Serialize)]
+pub enum EnergyQualifier {
+    Measured,
+    /// "AP" in ENSDF; no uncertainty is known.
+    Approximate,
+    /// "CA" in ENSDF.
+    Calculated,
+    /// "SY" in ENSDF, estimated from systematics.
+    Systematics,
+    /// "LT" or "LE" in ENSDF; the energy is a bound, not a value.
+    UpperLimit,
+    /// "GT" or "GE" in ENSDF; the energy is a bound, not a value.
+    LowerLimit,
+    /// Energy given as an offset from a level of unknown energy (e.g. "X+123").
+    RelativeToUnknownLevel,
+}
+
+impl EnergyQualifier {
+    /// Whether the energy is an actual value that query energies can be compared with.
+    pub fn is_matchable(&self) -> bool {
+        !matches!(
+            self,
+            EnergyQualifier::UpperLimit
+                | EnergyQualifier::LowerLimit
+                | EnergyQualifier::RelativeToUnknownLevel
+        )
+    }
+}
+
+impl fmt::Display for EnergyQualifier {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let s = match self {
+            EnergyQualifier::Measured => "",
+            EnergyQualifier::Approximate => "~",
+            EnergyQualifier::Calculated => "calc.",
+            EnergyQualifier::Systematics => "syst.",
+            EnergyQualifier::UpperLimit => "<",
+            EnergyQualifier::LowerLimit => ">",
+            EnergyQualifier::RelativeToUnknownLevel => "X+",
+        };
+        write!(f, "{s}")
+    }
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
 pub struct Transition {
-    pub parent: String,
-    pub daughter: String,
-    pub decay_type: String,
-    pub radiation_type: String,
-    pub transition_energy: String,
-    pub uncertainty: String,
+    pub decay: Decay,
+    pub radiation_type: RadiationType,
+    /// Energy in keV.
+    pub energy: f64,
+    /// Uncertainty of the energy in keV.
+    pub uncertainty: f64,
+    pub qualifier: EnergyQualifier,
+    /// Intensity per 100 decays of the parent.
     pub intensity: f64,
     pub lteb: f64,
     pub uteb: f64,
+    /// Mean energy in keV of a beta continuum, whose `energy` is the endpoint.
+    #[serde(default)]
+    pub mean_energy: Option<f64>,
+    /// Shell or line of X-rays and electrons (e.g. "K", "Kα1"), empty otherwise.
+    #[serde(default)]
+    pub line: String,
+}
+
+/// Encodes transitions in the format expected for `assets/database.bin`.
+pub fn encode_database(transitions: &[Transition]) -> Result<Vec<u8>, rmp_serde::encode::Error> {
+    rmp_serde::to_vec(transitions)
 }
 
 impl fmt::Display for Transition {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(f, " {:>7.7} ({})", self.transition_energy, self.uncertainty)
+        let energy = format!("{}{:.3}", self.qualifier, self.energy);
+        write!(f, " {:>12} ({:.3})", energy, self.uncertainty)?;
+        if !self.line.is_empty() {
+            write!(f, " {}", self.line)?;
+        }
+        if let Some(mean) = self.mean_energy {
+            write!(f, " mean {mean:.3}")?;
+        }
+        Ok(())
     }
 }
 
@@ -35,31 +195,66 @@ pub struct TransitionResult {
     pub found: bool,
 }
 
-fn energy_in_transition_range(e: &Energy, t: &Transition) -> bool {
+/// Whether the record `t` explains the query line `e`: same radiation type, overlapping
+/// energy windows and an intensity within the bounds per 100 decays of the line.
+pub fn energy_in_transition_range(e: &Energy, t: &Transition) -> bool {
+    if !t.qualifier.is_matchable() || e.radiation_type != Some(t.radiation_type) {
+        return false;
+    }
+    let absolute = e.intensity.iter().filter(|c| c.reference.is_none());
+    if !absolute.into_iter().all(|c| c.accepts(t.intensity)) {
+        return false;
+    }
     if (t.lteb <= e.lteb && e.lteb <= t.uteb) || (e.lteb <= t.lteb && t.lteb <= e.uteb) {
         return true;
     }
     false
 }
 
-fn filter_by_energy(e: &Energy, radiation_type: &RadiationType) -> HashSet<String> {
-    let parents_vec = DATABASE
-        .iter()
-        .filter(|t| energy_in_transition_range(e, t) && *radiation_type == t.radiation_type)
-        .map(|t| t.decay_type.clone());
+/// Decays whose daughter is `nuclide`, whatever the level they feed.
+pub fn precursors(nuclide: &Nuclide) -> Vec<Decay> {
+    let key = (nuclide.atomic_number, nuclide.mass_number);
+    DATABASE
+        .by_daughter
+        .get(&key)
+        .into_iter()
+        .flatten()
+        .filter_map(|dataset| DATABASE.decay(dataset).next())
+        .map(|t| t.decay.clone())
+        .collect()
+}
+
+/// Decays of `nuclide`, in the level it is in (see [`Nuclide::same_level`]), sorted by dataset.
+pub fn decays_of(nuclide: &Nuclide) -> Vec<Decay> {
+    let key = (nuclide.atomic_number, nuclide.mass_number);
+    DATABASE
+        .by_parent
+        .get(&key)
+        .into_iter()
+        .flatten()
+        .filter_map(|dataset| DATABASE.decay(dataset).next())
+        .filter(|t| t.decay.parent.same_level(nuclide))
+        .map(|t| t.decay.clone())
+        .collect()
+}
+
+fn filter_by_energy(e: &Energy) -> HashSet<String> {
+    let parents_vec = DATABASE.in_range(e).map(|t| t.decay.dataset.clone());
     HashSet::from_iter(parents_vec)
 }
 
-fn filter_by_decay_type(p: &String, radiation_type: &RadiationType) -> Vec<Transition> {
+/// Records of the decay dataset `p` with one of the radiation types given, sorted by energy.
+pub fn filter_by_decay_type(p: &str, radiation_types: &HashSet<RadiationType>) -> Vec<Transition> {
     let ts = DATABASE
-        .iter()
-        .filter(|t| t.decay_type == *p && *radiation_type == t.radiation_type)
+        .decay(p)
+        .filter(|t| radiation_types.contains(&t.radiation_type))
         .cloned()
         .collect();
     ts
 }
 
-fn mark_found_transitions(es: &Vec<Energy>, ts: Vec<Transition>) -> Vec<TransitionResult> {
+/// Flags the transitions matching any of `es`, keeping their order (by energy).
+pub fn mark_found_transitions(es: &[Energy], ts: Vec<Transition>) -> Vec<TransitionResult> {
     let mut ans: Vec<TransitionResult> = vec![];
     for t in ts {
         let mut found = false;
@@ -71,40 +266,82 @@ fn mark_found_transitions(es: &Vec<Energy>, ts: Vec<Transition>) -> Vec<Transiti
         }
         ans.push(TransitionResult { t, found });
     }
-    ans.sort_by(|a, b| {
-        a.t.transition_energy
-            .parse::<f64>()
-            .unwrap()
-            .partial_cmp(&b.t.transition_energy.parse::<f64>().unwrap())
-            .expect("Error while sorting transition energies")
-    });
     ans
 }
 
+/// Finds the decays with records matching at least one of the query energies and, when
+/// `require_all` is set, matching every energy not marked as optional.
+/// Energies only match records of their radiation type, which must be set; the records
+/// returned for each decay are those of the radiation types in the query.
 pub fn query_database(
-    energies: &Vec<Energy>,
-    radiation_type: &RadiationType,
-) -> Option<HashMap<String, Vec<TransitionResult>>> {
-    let mut decays: HashSet<String> = filter_by_energy(&energies[0], radiation_type);
-
+    energies: &[Energy],
+    require_all: bool,
+) -> HashMap<String, Vec<TransitionResult>> {
+    let mut decays: HashSet<String> = HashSet::new();
     for e in energies {
-        let current_decays = filter_by_energy(e, radiation_type);
-        decays.retain(|x| current_decays.contains(x));
+        decays.extend(filter_by_energy(e));
+    }
+
+    if require_all {
+        for e in energies
+            .iter()
+            .filter(|e| e.modifier == Modifier::Definitely)
+        {
+            let current_decays = filter_by_energy(e);
+            decays.retain(|x| current_decays.contains(x));
+        }
     }
 
     debug!("search finished");
     debug!("{}: {:?}", decays.len(), decays);
-    if decays.is_empty() {
-        return None;
-    }
 
+    let radiation_types: HashSet<RadiationType> =
+        energies.iter().filter_map(|e| e.radiation_type).collect();
     let mut results: HashMap<String, Vec<TransitionResult>> = HashMap::new();
     for p in decays {
         results.insert(
             p.clone(),
-            mark_found_transitions(energies, filter_by_decay_type(&p, radiation_type)),
+            mark_found_transitions(energies, filter_by_decay_type(&p, &radiation_types)),
         );
     }
 
-    Some(results)
+    results
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{decay, gamma, line};
+
+    #[test]
+    fn in_range_matches_a_linear_scan() {
+        let a = decay("A", 50, 120);
+        let mut transitions: Vec<Transition> = (0..40)
+            .map(|i| gamma(&a, 50.0 + 10.0 * i as f64, 1.0))
+            .collect();
+        // Wide enough to start well below windows it overlaps
+        let mut wide = gamma(&a, 200.0, 1.0);
+        (wide.lteb, wide.uteb) = (120.0, 280.0);
+        let mut limit = gamma(&a, 300.0, 1.0);
+        limit.qualifier = EnergyQualifier::UpperLimit;
+        let mut x_ray = gamma(&a, 300.0, 1.0);
+        x_ray.radiation_type = RadiationType::XRay;
+        transitions.extend([wide, limit, x_ray]);
+        let database = Database::new(transitions.clone());
+
+        for k in 0..100 {
+            let e = line(30.0 + 5.0 * k as f64, Modifier::Definitely);
+            let found: Vec<f64> = database.in_range(&e).map(|t| t.lteb).collect();
+            let mut expected: Vec<f64> = transitions
+                .iter()
+                .filter(|t| energy_in_transition_range(&e, t))
+                .map(|t| t.lteb)
+                .collect();
+            expected.sort_by(f64::total_cmp);
+            assert_eq!(found, expected, "window {}-{}", e.lteb, e.uteb);
+        }
+        // The wide record straddles the start of this window
+        let e = line(270.0, Modifier::Definitely);
+        assert!(database.in_range(&e).any(|t| t.lteb == 120.0));
+    }
 }
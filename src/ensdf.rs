@@ -0,0 +1,523 @@
+//! Reader for the 80-column ENSDF format used to build `assets/database.bin`.
+//!
+//! Only decay datasets are used. From each of them the identification (dataset ID),
+//! parent (`P`, with its half-life), normalization (`N`, with the branching ratio, and `PN`),
+//! level (`L`), gamma (`G`), alpha (`A`) and beta (`B` and `E`) records are read, together
+//! with the mean energies (`EAV`) in the continuation records of the betas; everything else,
+//! including comments, is skipped. Levels also give the isomers the decay feeds, see
+//! [`Decay::fed_isomers`].
+//! The field layout follows the ENSDF manual.
+
+use crate::app::RadiationType;
+use crate::database::{EnergyQualifier, Transition};
+use crate::nuclide::{time_unit_in_seconds, Decay, DecayMode, HalfLife, Nuclide};
+use log::{debug, warn};
+
+/// Counters reported after reading ENSDF files, so the origin of the database can be audited.
+#[derive(Debug, Default)]
+pub struct EnsdfSummary {
+    pub datasets: usize,
+    pub decay_datasets: usize,
+    pub transitions: usize,
+    pub skipped_records: usize,
+}
+
+impl std::fmt::Display for EnsdfSummary {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} datasets read, {} decay datasets used, {} transitions written, {} records skipped",
+            self.datasets, self.decay_datasets, self.transitions, self.skipped_records
+        )
+    }
+}
+
+/// Returns the columns `start..=end` (1-based, as in the ENSDF manual) of a record, trimmed.
+fn field(record: &str, start: usize, end: usize) -> &str {
+    let end = end.min(record.len());
+    if start > end {
+        return "";
+    }
+    record.get(start - 1..end).unwrap_or("").trim()
+}
+
+/// Record type (column 8) of a primary data record, `None` for comments and continuations.
+fn record_type(record: &str) -> Option<char> {
+    let bytes = record.as_bytes();
+    let continuation = bytes.get(5).copied().unwrap_or(b' ');
+    let comment = bytes.get(6).copied().unwrap_or(b' ');
+    if !(continuation == b' ' || continuation == b'1') || comment != b' ' {
+        return None;
+    }
+    Some(bytes.get(7).copied().unwrap_or(b' ') as char)
+}
+
+/// Record type (column 8) of a continuation record, e.g. "S B EAV=...".
+fn continuation_type(record: &str) -> Option<char> {
+    let bytes = record.as_bytes();
+    let continuation = bytes.get(5).copied().unwrap_or(b' ');
+    let comment = bytes.get(6).copied().unwrap_or(b' ');
+    if continuation == b' ' || continuation == b'1' || comment != b' ' {
+        return None;
+    }
+    Some(bytes.get(7).copied().unwrap_or(b' ') as char)
+}
+
+/// Mean energy of a beta from the "EAV=..." quantity of a continuation record.
+fn mean_energy(record: &str) -> Option<f64> {
+    field(record, 10, 80)
+        .split('$')
+        .find_map(|q| q.trim().strip_prefix("EAV="))
+        .and_then(|v| v.split_whitespace().next())
+        .and_then(|v| v.parse().ok())
+}
+
+/// Parses an ENSDF value with its uncertainty given in units of the last digits,
+/// e.g. ("661.657", "3") is 661.657 ± 0.003 and ("1.2E3", "2") is 1200 ± 200.
+/// Non-numeric uncertainties (AP, LT, GT, CA, SY, ...) are taken as zero.
+fn parse_value(value: &str, uncertainty: &str) -> Option<(f64, f64)> {
+    let v = value.parse::<f64>().ok()?;
+    let mantissa = value.split(['E', 'e']).next().unwrap_or(value);
+    let exponent: i32 = match value.split_once(['E', 'e']) {
+        Some((_, e)) => e.parse().ok()?,
+        None => 0,
+    };
+    let decimals = match mantissa.split_once('.') {
+        Some((_, d)) => d.len() as i32,
+        None => 0,
+    };
+    // Dividing by an exact power of ten rounds once, where multiplying by 10^-n (not exact)
+    // gives e.g. 0.30000000000000004 for ("661.6", "3")
+    let scale = exponent - decimals;
+    let u = match uncertainty.parse::<f64>() {
+        Ok(u) if scale < 0 => u / 10f64.powi(-scale),
+        Ok(u) => u * 10f64.powi(scale),
+        Err(_) => 0.0,
+    };
+    Some((v, u))
+}
+
+/// For an energy given relative to a level of unknown energy ("X+123.4", "123.4+Y" or "X"),
+/// returns the known offset.
+fn unknown_level_offset(value: &str) -> Option<&str> {
+    let is_label = |s: &str| s.len() == 1 && s.chars().all(|c| c.is_ascii_uppercase());
+    if is_label(value) {
+        return Some("0");
+    }
+    let (a, b) = value.split_once('+')?;
+    match (is_label(a), is_label(b)) {
+        (true, false) => Some(b),
+        (false, true) => Some(a),
+        _ => None,
+    }
+}
+
+/// Parses the energy and its uncertainty fields of a record, see [`parse_value`].
+fn parse_energy(value: &str, uncertainty: &str) -> Option<(f64, f64, EnergyQualifier)> {
+    if let Some(offset) = unknown_level_offset(value) {
+        let (e, de) = parse_value(offset, uncertainty)?;
+        return Some((e, de, EnergyQualifier::RelativeToUnknownLevel));
+    }
+    let (e, de) = parse_value(value, uncertainty)?;
+    let qualifier = match uncertainty {
+        "AP" => EnergyQualifier::Approximate,
+        "CA" => EnergyQualifier::Calculated,
+        "SY" => EnergyQualifier::Systematics,
+        "LT" | "LE" => EnergyQualifier::UpperLimit,
+        "GT" | "GE" => EnergyQualifier::LowerLimit,
+        _ => EnergyQualifier::Measured,
+    };
+    Some((e, de, qualifier))
+}
+
+/// Endpoint energy of a beta feeding the daughter level at `level` keV, computed as
+/// Q + E(parent level) - E(level), minus 2mc² for a β+.
+fn beta_endpoint(
+    q_value: Option<(f64, f64)>,
+    parent_level: f64,
+    level: Option<f64>,
+    positron: bool,
+) -> Option<(f64, f64, EnergyQualifier)> {
+    let (q, dq) = q_value?;
+    let pair = if positron { 1021.998 } else { 0.0 };
+    let e = q + parent_level - level? - pair;
+    (e > 0.0).then_some((e, dq, EnergyQualifier::Calculated))
+}
+
+/// Parses the half-life fields of a parent record, e.g. ("30.08 Y", "9"). Half-lives given as
+/// level widths (in eV) or as limits are ignored.
+fn parse_half_life(value: &str, uncertainty: &str) -> Option<HalfLife> {
+    let (value, unit) = value.split_once(' ')?;
+    let unit = unit.trim();
+    time_unit_in_seconds(unit)?;
+    let (v, dv) = parse_value(value, uncertainty)?;
+    Some(HalfLife {
+        value: v,
+        uncertainty: dv,
+        unit: unit.to_string(),
+    })
+}
+
+fn parse_number(value: &str, default: f64) -> f64 {
+    value.parse::<f64>().unwrap_or(default)
+}
+
+struct Dataset<'a> {
+    nucid: &'a str,
+    dsid: &'a str,
+    records: Vec<&'a str>,
+}
+
+impl Dataset<'_> {
+    fn is_decay(&self) -> bool {
+        self.dsid.contains(" DECAY")
+    }
+
+    /// Builds the decay from the dataset ID (e.g. "137CS B- DECAY (30.08 Y)") and parent record.
+    fn decay(&self, parent: &str) -> Option<Decay> {
+        let mode: Vec<&str> = self
+            .dsid
+            .split_whitespace()
+            .skip(1)
+            .take_while(|w| *w != "DECAY")
+            .collect();
+        let mut parent_nuclide = Nuclide::from_nucid(field(parent, 1, 5))?;
+        parent_nuclide.level_energy = parse_energy(field(parent, 10, 19), "")
+            .map(|(e, _, _)| e)
+            .unwrap_or(0.0);
+        Some(Decay {
+            parent: parent_nuclide,
+            mode: DecayMode::from_ensdf(&mode.join(" ")),
+            daughter: Nuclide::from_nucid(self.nucid)?,
+            dataset: self.dsid.to_string(),
+            half_life: parse_half_life(field(parent, 40, 49), field(parent, 50, 55)),
+            branching_ratio: self.first('N').and_then(|n| field(n, 32, 39).parse().ok()),
+            fed_isomers: vec![],
+        })
+    }
+
+    fn first(&self, kind: char) -> Option<&str> {
+        self.records
+            .iter()
+            .copied()
+            .find(|r| record_type(r) == Some(kind))
+    }
+
+    /// Production normalization record, i.e. `PN` in columns 7-8.
+    fn production_normalization(&self) -> Option<&str> {
+        self.records
+            .iter()
+            .copied()
+            .find(|r| r.get(5..8) == Some(" PN") || r.get(5..8) == Some("1PN"))
+    }
+}
+
+fn split_datasets(input: &str) -> Vec<Dataset<'_>> {
+    let mut datasets = Vec::new();
+    let mut records: Vec<&str> = Vec::new();
+    for line in input.lines().chain(std::iter::once("")) {
+        let line = line.trim_end_matches('\r');
+        if !line.trim().is_empty() {
+            records.push(line);
+            continue;
+        }
+        if let Some(id) = records.first() {
+            datasets.push(Dataset {
+                nucid: field(id, 1, 5),
+                dsid: field(id, 10, 39),
+                records: std::mem::take(&mut records),
+            });
+        }
+    }
+    datasets
+}
+
+/// Reads the decay datasets in `input` (the contents of one or more ENSDF files)
+/// and returns one `Transition` for each gamma, alpha and beta record.
+pub fn read_transitions(input: &str, summary: &mut EnsdfSummary) -> Vec<Transition> {
+    let mut transitions: Vec<Transition> = Vec::new();
+    for dataset in split_datasets(input) {
+        summary.datasets += 1;
+        if !dataset.is_decay() {
+            continue;
+        }
+        let Some(parent) = dataset.first('P') else {
+            debug!("{}: decay dataset without parent record", dataset.dsid);
+            continue;
+        };
+        let Some(decay) = dataset.decay(parent) else {
+            warn!("{}: cannot identify the parent and daughter", dataset.dsid);
+            continue;
+        };
+        summary.decay_datasets += 1;
+
+        // Multipliers converting relative intensities into intensities per 100 decays. NB only
+        // normalizes β- and EC intensities; alpha intensities are per 100 alpha decays, so
+        // they only take the branching ratio.
+        let branching_ratio = dataset
+            .first('N')
+            .map_or(1.0, |n| parse_number(field(n, 32, 39), 1.0));
+        let alpha_norm = branching_ratio;
+        let (gamma_norm, beta_norm) = match dataset.production_normalization() {
+            Some(pn) => (
+                parse_number(field(pn, 10, 19), 1.0),
+                parse_number(field(pn, 42, 49), 1.0),
+            ),
+            None => match dataset.first('N') {
+                Some(n) => (
+                    parse_number(field(n, 10, 19), 1.0) * branching_ratio,
+                    parse_number(field(n, 42, 49), 1.0) * branching_ratio,
+                ),
+                None => (1.0, 1.0),
+            },
+        };
+
+        // Beta endpoints left blank in the records are computed from the Q value
+        let q_value = parse_value(field(parent, 65, 74), field(parent, 75, 76));
+        let mut level = None;
+        let mut current_beta: Option<usize> = None;
+        // Isomeric levels met so far with their feeding per 100 decays, and the index of the
+        // current level among them
+        let mut isomers: Vec<(f64, f64)> = vec![];
+        let mut isomer = None;
+        let first_transition = transitions.len();
+
+        for record in &dataset.records {
+            if let Some(kind) = continuation_type(record) {
+                if let (Some(i), 'B' | 'E') = (current_beta, kind) {
+                    let t = &mut transitions[i];
+                    t.mean_energy = t.mean_energy.or(mean_energy(record));
+                }
+                continue;
+            }
+            current_beta = None;
+            let kind = record_type(record);
+            let (radiation_type, norm) = match kind {
+                Some('G') => (RadiationType::Gamma, gamma_norm),
+                Some('A') => (RadiationType::Alpha, alpha_norm),
+                Some('B') | Some('E') => (RadiationType::Beta, beta_norm),
+                Some('L') => {
+                    level = parse_energy(field(record, 10, 19), "")
+                        .filter(|(_, _, q)| *q == EnergyQualifier::Measured)
+                        .map(|(e, _, _)| e);
+                    // Flagged in the MS field (column 78) or long-lived enough to matter
+                    let long_lived = parse_half_life(field(record, 40, 49), field(record, 50, 55))
+                        .is_some_and(|t| t.seconds() >= 1e-3);
+                    isomer = match level {
+                        Some(e) if e > 0.0 && (field(record, 78, 78) == "M" || long_lived) => {
+                            isomers.push((e, 0.0));
+                            Some(isomers.len() - 1)
+                        }
+                        _ => None,
+                    };
+                    continue;
+                }
+                _ => continue,
+            };
+            match kind {
+                // Direct feeding: IB, plus IE for electron capture
+                Some('A' | 'B' | 'E') => {
+                    if let Some(i) = isomer {
+                        let mut feeding = parse_number(field(record, 22, 29), 0.0);
+                        if kind == Some('E') {
+                            feeding += parse_number(field(record, 32, 39), 0.0);
+                        }
+                        isomers[i].1 += feeding * norm;
+                    }
+                }
+                // Gammas of a higher level ending in an isomer: total intensity TI, or
+                // RI·(1 + CC) when not given
+                _ => {
+                    let energy = field(record, 10, 19).parse::<f64>().ok();
+                    let end = level.zip(energy).map(|(from, e)| from - e);
+                    let fed = end
+                        .and_then(|end| isomers.iter_mut().find(|(l, _)| (end - *l).abs() < 1.0));
+                    if let Some(fed) = fed {
+                        let total = match field(record, 65, 74).parse::<f64>() {
+                            Ok(ti) => ti,
+                            Err(_) => {
+                                parse_number(field(record, 22, 29), 0.0)
+                                    * (1.0 + parse_number(field(record, 56, 62), 0.0))
+                            }
+                        };
+                        fed.1 += total * norm;
+                    }
+                }
+            }
+            // Electron capture branches without a β+ component
+            if kind == Some('E') && field(record, 22, 29).is_empty() {
+                continue;
+            }
+            let energy = field(record, 10, 19);
+            let uncertainty = field(record, 20, 21);
+            let parsed = match parse_energy(energy, uncertainty) {
+                None if radiation_type == RadiationType::Beta && energy.is_empty() => {
+                    let positron = kind == Some('E');
+                    beta_endpoint(q_value, decay.parent.level_energy, level, positron)
+                }
+                parsed => parsed,
+            };
+            let Some((e, de, qualifier)) = parsed else {
+                warn!("{}: skipping record with energy {energy:?}", dataset.dsid);
+                summary.skipped_records += 1;
+                continue;
+            };
+            if radiation_type == RadiationType::Beta {
+                current_beta = Some(transitions.len());
+            }
+            transitions.push(Transition {
+                decay: decay.clone(),
+                radiation_type,
+                energy: e,
+                uncertainty: de,
+                qualifier,
+                intensity: parse_number(field(record, 22, 29), 0.0) * norm,
+                lteb: e - de,
+                uteb: e + de,
+                mean_energy: None,
+                line: String::new(),
+            });
+            summary.transitions += 1;
+        }
+        // Per decay through this dataset, the branching ratio being counted by the chains
+        let per_decay = 100.0 * decay.branching_ratio.unwrap_or(1.0);
+        let fed_isomers: Vec<(f64, f64)> = isomers
+            .into_iter()
+            .filter(|(_, feeding)| *feeding > 0.0)
+            .map(|(e, feeding)| (e, (feeding / per_decay).min(1.0)))
+            .collect();
+        for t in &mut transitions[first_transition..] {
+            t.decay.fed_isomers = fed_isomers.clone();
+        }
+    }
+    transitions
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::assert_close;
+
+    /// Two decay datasets, trimmed down from the evaluations.
+    const DATASETS: &str = concat!(
+        "137BA    137CS B- DECAY (30.08 Y)\n",
+        "137BA cc  comment\n",
+        "137CS  P 0.0         7/2+              30.08 Y   9              1175.63   17\n",
+        "137BA  N 0.944                 1.0       1.0\n",
+        "137BA  L 0.0\n",
+        "137BA  B             5.64    9\n",
+        "137BAS B EAV=416.26 8\n",
+        "137BA  L 661.659   3                   2.552 M   1                           M1\n",
+        "137BA  B             94.36   20\n",
+        "137BAS B EAV=174.32 7$LOGFT=9.61\n",
+        "137BA  G 661.657   3 90.1    2\n",
+        "137BA  G 283.5     1 6.2E-4\n",
+        "137BA  G X+12.3\n",
+        "\n",
+        "237NP    241AM A DECAY (432.6 Y)\n",
+        "241AM  P 0.0                           432.6 Y   6\n",
+        "237NP  N 0.36                  1.0       1.0\n",
+        "237NP  A 5485.56   1284.8    5\n",
+        "237NP  A 5442.80   1313.1    3\n",
+        "237NP  G 59.5409   1 100\n",
+    );
+
+    fn find(transitions: &[Transition], radiation_type: RadiationType, energy: f64) -> &Transition {
+        transitions
+            .iter()
+            .find(|t| t.radiation_type == radiation_type && (t.energy - energy).abs() < 1e-6)
+            .unwrap_or_else(|| panic!("no {radiation_type} at {energy} keV"))
+    }
+
+    #[test]
+    fn fields_are_one_based_and_trimmed() {
+        let record = "137CS  P 0.0         7/2+              30.08 Y   9";
+        assert_eq!(field(record, 1, 5), "137CS");
+        assert_eq!(field(record, 10, 19), "0.0");
+        assert_eq!(field(record, 40, 49), "30.08 Y");
+        assert_eq!(field(record, 50, 55), "9");
+        assert_eq!(field(record, 65, 74), "");
+        assert_eq!(record_type(record), Some('P'));
+        assert_eq!(record_type("137BA cc  comment"), None);
+        assert_eq!(continuation_type("137BAS B EAV=416.26 8"), Some('B'));
+        assert_eq!(
+            mean_energy("137BAS B EAV=174.32 7$LOGFT=9.61"),
+            Some(174.32)
+        );
+    }
+
+    #[test]
+    fn values_take_the_uncertainty_in_their_last_digits() {
+        assert_eq!(parse_value("661.657", "3"), Some((661.657, 0.003)));
+        assert_eq!(parse_value("1.2E3", "2"), Some((1200.0, 200.0)));
+        assert_eq!(parse_value("6.2E-4", ""), Some((6.2e-4, 0.0)));
+        assert_eq!(parse_value("90", "AP"), Some((90.0, 0.0)));
+        let (e, de) = parse_value("4.468E9", "3").unwrap();
+        assert_close(e, 4.468e9);
+        assert_close(de, 3e6);
+        assert_eq!(parse_value("X", ""), None);
+    }
+
+    #[test]
+    fn uncertainties_are_rounded_once() {
+        assert_eq!(parse_value("661.6", "3"), Some((661.6, 0.3)));
+        assert_eq!(parse_value("5.5E-3", "7"), Some((5.5e-3, 7e-4)));
+        // As close as the decimal written out, for every uncertainty and number of decimals
+        for u in 1..100 {
+            for decimals in 0..8 {
+                let value = format!("1.{}", "0".repeat(decimals));
+                let value = value.trim_end_matches('.');
+                let expected: f64 = format!("{u}e-{decimals}").parse().unwrap();
+                assert_eq!(parse_value(value, &u.to_string()).unwrap().1, expected);
+            }
+        }
+    }
+
+    #[test]
+    fn energies_keep_their_qualifier() {
+        assert_eq!(
+            parse_energy("X+12.3", ""),
+            Some((12.3, 0.0, EnergyQualifier::RelativeToUnknownLevel))
+        );
+        assert_eq!(
+            parse_energy("283.5", "AP"),
+            Some((283.5, 0.0, EnergyQualifier::Approximate))
+        );
+        let half_life = parse_half_life("30.08 Y", "9").unwrap();
+        assert_eq!((half_life.value, half_life.unit.as_str()), (30.08, "Y"));
+        assert_eq!(parse_half_life("2.3 EV", "1"), None);
+    }
+
+    #[test]
+    fn intensities_are_per_100_decays() {
+        let mut summary = EnsdfSummary::default();
+        let transitions = read_transitions(DATASETS, &mut summary);
+        assert_eq!(summary.decay_datasets, 2);
+        // NR·BR for the gammas of 137Cs
+        let gamma = find(&transitions, RadiationType::Gamma, 661.657);
+        assert_close(gamma.intensity, 90.1 * 0.944);
+        assert_close(gamma.uncertainty, 0.003);
+        // Beta endpoint from the Q value, with the mean energy of the continuation record
+        let beta = find(&transitions, RadiationType::Beta, 1175.63 - 661.659);
+        assert_close(beta.intensity, 94.36);
+        assert_eq!(beta.mean_energy, Some(174.32));
+        // Alphas only take the branching ratio
+        let alpha = find(&transitions, RadiationType::Alpha, 5485.56);
+        assert_close(alpha.intensity, 84.8);
+        assert_close(
+            find(&transitions, RadiationType::Gamma, 59.5409).intensity,
+            36.0,
+        );
+    }
+
+    #[test]
+    fn isomers_fed_are_recorded() {
+        let transitions = read_transitions(DATASETS, &mut EnsdfSummary::default());
+        let cs = &find(&transitions, RadiationType::Gamma, 661.657).decay;
+        assert_eq!(cs.fed_isomers.len(), 1);
+        assert_close(cs.fed_isomers[0].0, 661.659);
+        assert_close(cs.fed_isomers[0].1, 0.9436);
+        let am = &find(&transitions, RadiationType::Alpha, 5485.56).decay;
+        assert!(am.fed_isomers.is_empty());
+    }
+}
@@ -0,0 +1,186 @@
+use crate::app::PrintMode;
+use crate::database::EnergyQualifier;
+use crate::nuclide::HalfLife;
+use crate::search::SearchResult;
+use serde::Serialize;
+
+#[derive(PartialEq, serde::Deserialize, serde::Serialize)]
+#[serde(tag = "type")]
+pub enum OutputFormat {
+    Text,
+    Json,
+    Csv,
+}
+
+impl std::str::FromStr for OutputFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "text" => Ok(OutputFormat::Text),
+            "json" => Ok(OutputFormat::Json),
+            "csv" => Ok(OutputFormat::Csv),
+            _ => Err(format!("unknown output format: {s}")),
+        }
+    }
+}
+
+const CSV_HEADER: [&str; 15] = [
+    "decay_type",
+    "score",
+    "optional_lines",
+    "parent",
+    "mode",
+    "daughter",
+    "half_life_s",
+    "radiation",
+    "line",
+    "energy",
+    "uncertainty",
+    "qualifier",
+    "intensity",
+    "mean_energy",
+    "found",
+];
+
+/// One row of the machine-readable output, i.e. one radiation record of a candidate decay.
+#[derive(Serialize)]
+struct ResultRecord<'a> {
+    decay_type: &'a str,
+    score: f64,
+    /// Optional query lines explained by the decay, 1-based and separated by spaces.
+    optional_lines: String,
+    parent: String,
+    mode: String,
+    daughter: String,
+    /// Half-life of the parent in seconds.
+    half_life_s: Option<f64>,
+    radiation: String,
+    line: &'a str,
+    energy: f64,
+    uncertainty: f64,
+    qualifier: EnergyQualifier,
+    intensity: f64,
+    mean_energy: Option<f64>,
+    found: bool,
+}
+
+fn result_records<'a>(results: &'a SearchResult, print_mode: &PrintMode) -> Vec<ResultRecord<'a>> {
+    let mut records = Vec::new();
+    for c in &results.candidates {
+        for r in &c.transitions {
+            if *print_mode == PrintMode::OnlyMatches && !r.found {
+                continue;
+            }
+            records.push(ResultRecord {
+                decay_type: &c.decay.dataset,
+                score: c.score,
+                optional_lines: c
+                    .optional_lines
+                    .iter()
+                    .map(|i| results.query_lines[*i].to_string())
+                    .collect::<Vec<String>>()
+                    .join(" "),
+                parent: r.t.decay.parent.to_string(),
+                mode: r.t.decay.mode.to_string(),
+                daughter: r.t.decay.daughter.to_string(),
+                half_life_s: r.t.decay.half_life.as_ref().map(HalfLife::seconds),
+                radiation: r.t.radiation_type.to_string(),
+                line: &r.t.line,
+                energy: r.t.energy,
+                uncertainty: r.t.uncertainty,
+                qualifier: r.t.qualifier,
+                intensity: r.t.intensity,
+                mean_energy: r.t.mean_energy,
+                found: r.found,
+            });
+        }
+    }
+    records
+}
+
+pub fn results_to_json(results: &SearchResult, print_mode: &PrintMode) -> Result<String, String> {
+    serde_json::to_string_pretty(&result_records(results, print_mode)).map_err(|e| e.to_string())
+}
+
+pub fn results_to_csv(results: &SearchResult, print_mode: &PrintMode) -> Result<String, String> {
+    // The header is written by hand so that an empty result still yields a valid table
+    let mut writer = csv::WriterBuilder::new()
+        .has_headers(false)
+        .from_writer(vec![]);
+    writer.write_record(CSV_HEADER).map_err(|e| e.to_string())?;
+    for record in result_records(results, print_mode) {
+        writer.serialize(record).map_err(|e| e.to_string())?;
+    }
+    let bytes = writer.into_inner().map_err(|e| e.to_string())?;
+    String::from_utf8(bytes).map_err(|e| e.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database::TransitionResult;
+    use crate::search::Candidate;
+    use crate::testing::{decay, gamma};
+
+    /// One candidate with a matched and an unmatched record, and a dataset ID to be quoted.
+    fn results() -> SearchResult {
+        let a = decay("120SN B- DECAY, \"TEST\"", 50, 120);
+        let transitions = vec![
+            TransitionResult {
+                t: gamma(&a, 100.0, 10.0),
+                found: true,
+            },
+            TransitionResult {
+                t: gamma(&a, 200.0, 5.0),
+                found: false,
+            },
+        ];
+        let candidate = Candidate {
+            decay: a,
+            transitions,
+            matched_lines: vec![0, 1],
+            optional_lines: vec![1],
+            intensity_mismatches: vec![],
+            matched_intensity: 10.0,
+            missing_intensity: 0.0,
+            score: 0.5,
+            grown_in_from: None,
+            chain: vec![],
+        };
+        SearchResult {
+            candidates: vec![candidate],
+            unmatched_lines: vec![],
+            background_lines: vec![],
+            query_lines: vec![1, 3],
+        }
+    }
+
+    #[test]
+    fn csv_columns_and_quoting() {
+        let csv = results_to_csv(&results(), &PrintMode::Everything).unwrap();
+        let lines: Vec<&str> = csv.lines().collect();
+        assert_eq!(lines[0], CSV_HEADER.join(","));
+        assert_eq!(
+            lines[1..],
+            [
+                "\"120SN B- DECAY, \"\"TEST\"\"\",0.5,3,120Sn,β-,120Sb,,gamma,,100.0,0.5,Measured,10.0,,true",
+                "\"120SN B- DECAY, \"\"TEST\"\"\",0.5,3,120Sn,β-,120Sb,,gamma,,200.0,0.5,Measured,5.0,,false",
+            ]
+        );
+        let csv = results_to_csv(&results(), &PrintMode::OnlyMatches).unwrap();
+        assert_eq!(csv.lines().count(), 2);
+    }
+
+    #[test]
+    fn json_keys_follow_the_csv_columns() {
+        let json = results_to_json(&results(), &PrintMode::OnlyMatches).unwrap();
+        let positions: Vec<usize> = CSV_HEADER
+            .iter()
+            .map(|key| json.find(&format!("\"{key}\":")).unwrap())
+            .collect();
+        assert!(positions.windows(2).all(|w| w[0] < w[1]), "{json}");
+        assert!(json.contains(r#""decay_type": "120SN B- DECAY, \"TEST\"""#));
+        assert!(json.contains(r#""half_life_s": null"#));
+    }
+}
@@ -0,0 +1,247 @@
+//! Small least-squares helpers for spectrum analysis.
+
+/// Solves the linear system `a·x = b` by Gaussian elimination with partial pivoting,
+/// `None` when the matrix is singular.
+pub fn solve(mut a: Vec<Vec<f64>>, mut b: Vec<f64>) -> Option<Vec<f64>> {
+    let n = b.len();
+    for col in 0..n {
+        let pivot = (col..n).max_by(|&i, &j| a[i][col].abs().total_cmp(&a[j][col].abs()))?;
+        if a[pivot][col].abs() < 1e-300 {
+            return None;
+        }
+        a.swap(col, pivot);
+        b.swap(col, pivot);
+        for row in col + 1..n {
+            let factor = a[row][col] / a[col][col];
+            for k in col..n {
+                a[row][k] -= factor * a[col][k];
+            }
+            b[row] -= factor * b[col];
+        }
+    }
+    let mut x = vec![0.0; n];
+    for row in (0..n).rev() {
+        let sum: f64 = (row + 1..n).map(|k| a[row][k] * x[k]).sum();
+        x[row] = (b[row] - sum) / a[row][row];
+    }
+    Some(x)
+}
+
+/// Least-squares polynomial through the points `(x, y)`: the coefficients `c` of
+/// `y = c[0] + c[1]·x + ... + c[degree]·x^degree`. `None` when there are not enough distinct
+/// points to fix them.
+pub fn fit_polynomial(points: &[(f64, f64)], degree: usize) -> Option<Vec<f64>> {
+    let n = degree + 1;
+    let mut xs: Vec<f64> = points.iter().map(|p| p.0).collect();
+    xs.sort_by(f64::total_cmp);
+    xs.dedup();
+    if xs.len() < n {
+        return None;
+    }
+    // Scaled so that the normal equations stay well conditioned for channel numbers
+    let scale = points.iter().map(|p| p.0.abs()).fold(1.0, f64::max);
+    let mut a = vec![vec![0.0; n]; n];
+    let mut b = vec![0.0; n];
+    for &(x, y) in points {
+        let powers: Vec<f64> = (0..n).map(|k| (x / scale).powi(k as i32)).collect();
+        for i in 0..n {
+            for j in 0..n {
+                a[i][j] += powers[i] * powers[j];
+            }
+            b[i] += powers[i] * y;
+        }
+    }
+    let c = solve(a, b)?;
+    Some(
+        c.iter()
+            .enumerate()
+            .map(|(k, c)| c / scale.powi(k as i32))
+            .collect(),
+    )
+}
+
+/// Value at `x` of the polynomial of coefficients `c`, see [`fit_polynomial`].
+pub fn polynomial(c: &[f64], x: f64) -> f64 {
+    c.iter().rev().fold(0.0, |acc, c| acc * x + c)
+}
+
+/// Inverse of the square matrix `a`, `None` when it is singular.
+pub fn invert(a: &[Vec<f64>]) -> Option<Vec<Vec<f64>>> {
+    let n = a.len();
+    let columns = (0..n)
+        .map(|j| solve(a.to_vec(), (0..n).map(|i| (i == j) as u8 as f64).collect()))
+        .collect::<Option<Vec<Vec<f64>>>>()?;
+    Some(
+        (0..n)
+            .map(|i| (0..n).map(|j| columns[j][i]).collect())
+            .collect(),
+    )
+}
+
+/// Result of a weighted least-squares fit, see [`fit_least_squares`].
+pub struct LeastSquares {
+    pub params: Vec<f64>,
+    /// Covariance of the parameters, scaled by the reduced χ² when that exceeds 1.
+    pub covariance: Vec<Vec<f64>>,
+    /// χ² per degree of freedom.
+    pub reduced_chi2: f64,
+}
+
+impl LeastSquares {
+    /// Standard uncertainty of parameter `i`.
+    pub fn uncertainty(&self, i: usize) -> f64 {
+        self.covariance[i][i].max(0.0).sqrt()
+    }
+}
+
+/// Fits `model` to the points `(x[i], y[i])` of weights `w[i]` (1/variance) by the
+/// Levenberg-Marquardt method, starting from `params`. `model(x, p)` returns the value of the
+/// model at `x` and its gradient with respect to the parameters.
+pub fn fit_least_squares(
+    x: &[f64],
+    y: &[f64],
+    w: &[f64],
+    mut params: Vec<f64>,
+    model: impl Fn(f64, &[f64]) -> (f64, Vec<f64>),
+) -> Option<LeastSquares> {
+    let n = params.len();
+    let dof = x.len().checked_sub(n).filter(|&d| d > 0)? as f64;
+    let chi2 = |p: &[f64]| -> f64 {
+        (0..x.len())
+            .map(|i| w[i] * (y[i] - model(x[i], p).0).powi(2))
+            .sum()
+    };
+    // Normal equations J^T W J and J^T W r at `p`
+    let normal = |p: &[f64]| {
+        let mut a = vec![vec![0.0; n]; n];
+        let mut g = vec![0.0; n];
+        for i in 0..x.len() {
+            let (f, grad) = model(x[i], p);
+            for j in 0..n {
+                for k in 0..n {
+                    a[j][k] += w[i] * grad[j] * grad[k];
+                }
+                g[j] += w[i] * grad[j] * (y[i] - f);
+            }
+        }
+        (a, g)
+    };
+    let mut current = chi2(&params);
+    let mut lambda = 1e-3;
+    for _ in 0..200 {
+        let (a, g) = normal(&params);
+        let mut improved = false;
+        while lambda < 1e10 {
+            let mut damped = a.clone();
+            for (j, row) in damped.iter_mut().enumerate() {
+                row[j] *= 1.0 + lambda;
+            }
+            let Some(step) = solve(damped, g.clone()) else {
+                lambda *= 10.0;
+                continue;
+            };
+            let trial: Vec<f64> = params.iter().zip(&step).map(|(p, s)| p + s).collect();
+            let value = chi2(&trial);
+            if value.is_finite() && value <= current {
+                let converged = current - value <= 1e-10 * current.max(1e-300);
+                params = trial;
+                current = value;
+                lambda = (lambda / 10.0).max(1e-12);
+                improved = !converged;
+                break;
+            }
+            lambda *= 10.0;
+        }
+        if !improved {
+            break;
+        }
+    }
+    let reduced_chi2 = current / dof;
+    let mut covariance = invert(&normal(&params).0)?;
+    for row in covariance.iter_mut() {
+        for c in row.iter_mut() {
+            *c *= reduced_chi2.max(1.0);
+        }
+    }
+    Some(LeastSquares {
+        params,
+        covariance,
+        reduced_chi2,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::assert_close;
+
+    #[test]
+    fn solve_and_invert() {
+        let a = vec![vec![0.0, 2.0], vec![1.0, 1.0]];
+        assert_eq!(solve(a.clone(), vec![4.0, 3.0]), Some(vec![1.0, 2.0]));
+        assert_eq!(invert(&a), Some(vec![vec![-0.5, 1.0], vec![0.5, 0.0]]));
+        assert_eq!(
+            solve(vec![vec![1.0, 2.0], vec![2.0, 4.0]], vec![1.0, 2.0]),
+            None
+        );
+    }
+
+    #[test]
+    fn fit_polynomial_through_points() {
+        // y = 1 + x + x² through three points, then a least-squares line
+        let c = fit_polynomial(&[(0.0, 1.0), (1.0, 3.0), (2.0, 7.0)], 2).unwrap();
+        for (c, expected) in c.iter().zip([1.0, 1.0, 1.0]) {
+            assert_close(*c, expected);
+        }
+        let c = fit_polynomial(&[(0.0, 0.0), (1.0, 2.0), (2.0, 2.0), (3.0, 4.0)], 1).unwrap();
+        assert_close(c[0], 0.2);
+        assert_close(c[1], 1.2);
+        // Channel numbers as large as in real spectra
+        let points: Vec<(f64, f64)> = [100.0, 4000.0, 8000.0]
+            .iter()
+            .map(|&x| (x, 0.3 + 0.25 * x + 1e-7 * x * x))
+            .collect();
+        let c = fit_polynomial(&points, 2).unwrap();
+        assert_close(polynomial(&c, 6000.0), 0.3 + 0.25 * 6000.0 + 1e-7 * 36e6);
+        // Repeated channels do not fix the coefficients
+        assert_eq!(fit_polynomial(&[(1.0, 1.0), (1.0, 2.0)], 1), None);
+    }
+
+    #[test]
+    fn fit_least_squares_exponential() {
+        // y = 50·exp(-x/4), from a start far off
+        let x: Vec<f64> = (0..20).map(f64::from).collect();
+        let y: Vec<f64> = x.iter().map(|x| 50.0 * (-x / 4.0).exp()).collect();
+        let w = vec![1.0; x.len()];
+        let model = |x: f64, p: &[f64]| {
+            let e = (-x / p[1]).exp();
+            (p[0] * e, vec![e, p[0] * e * x / (p[1] * p[1])])
+        };
+        let fit = fit_least_squares(&x, &y, &w, vec![10.0, 1.0], model).unwrap();
+        assert!((fit.params[0] - 50.0).abs() < 1e-6);
+        assert!((fit.params[1] - 4.0).abs() < 1e-6);
+        assert!(fit.reduced_chi2 < 1e-12);
+        // As many points as parameters leave no degree of freedom
+        assert!(fit_least_squares(&x[..2], &y[..2], &w[..2], vec![10.0, 1.0], model).is_none());
+    }
+
+    #[test]
+    fn fit_least_squares_line_uncertainties() {
+        // Straight line through points of unit variance: the covariance of the slope is
+        // 1/Σ(x - x̄)²
+        let x = [0.0, 1.0, 2.0, 3.0];
+        let y = [1.0, 3.0, 5.0, 7.0];
+        let model = |x: f64, p: &[f64]| (p[0] + p[1] * x, vec![1.0, x]);
+        let fit = fit_least_squares(&x, &y, &[1.0; 4], vec![0.0, 0.0], model).unwrap();
+        assert_close(fit.params[0], 1.0);
+        assert_close(fit.params[1], 2.0);
+        assert_close(fit.covariance[1][1], 0.2);
+        assert_close(fit.uncertainty(0), 0.7f64.sqrt());
+    }
+
+    #[test]
+    fn polynomial_value() {
+        assert_eq!(polynomial(&[1.0, 2.0, 3.0], 2.0), 17.0);
+        assert_eq!(polynomial(&[], 2.0), 0.0);
+    }
+}
@@ -1,6 +1,43 @@
 #![warn(clippy::all, rust_2018_idioms)]
 
 mod app;
+mod atomic;
+mod background;
+mod calibration;
+mod chain;
+mod cooling;
+mod csv_rows;
 mod database;
+mod ensdf;
+mod export;
+mod fit;
+mod nuclide;
+mod peaks;
 mod query_parser;
-pub use app::TemplateApp;
+mod resolution;
+mod results_table;
+mod search;
+mod spectrum;
+mod spectrum_plot;
+#[cfg(test)]
+mod testing;
+pub use app::{PrintMode, RadiationType, TemplateApp};
+pub use atomic::read_atomic_radiations;
+pub use background::{default_background, read_background, BackgroundLine};
+pub use calibration::{
+    read_calibration_points, CalibrationPoint, EnergyCalibration, REFERENCE_LINES,
+};
+pub use database::{encode_database, EnergyQualifier, Transition, TransitionResult};
+pub use ensdf::{read_transitions, EnsdfSummary};
+pub use export::OutputFormat;
+pub use nuclide::{Decay, DecayMode, HalfLife, Nuclide};
+pub use peaks::{find_peaks, Peak, PeakFit, PeakSearch};
+pub use query_parser::{
+    format_results, parse_duration, parse_query, search_energies, Comparison, Diagnostic, Energy,
+    IntensityConstraint, Modifier, Query,
+};
+pub use resolution::ResolutionModel;
+pub use search::{
+    search, Candidate, CandidateOrder, HalfLifeFilter, SearchError, SearchOptions, SearchResult,
+};
+pub use spectrum::{read_spectrum, Spectrum};
@@ -0,0 +1,199 @@
+use serde::{Deserialize, Serialize};
+
+const ELEMENTS: [&str; 119] = [
+    "n", "H", "He", "Li", "Be", "B", "C", "N", "O", "F", "Ne", "Na", "Mg", "Al", "Si", "P", "S",
+    "Cl", "Ar", "K", "Ca", "Sc", "Ti", "V", "Cr", "Mn", "Fe", "Co", "Ni", "Cu", "Zn", "Ga", "Ge",
+    "As", "Se", "Br", "Kr", "Rb", "Sr", "Y", "Zr", "Nb", "Mo", "Tc", "Ru", "Rh", "Pd", "Ag", "Cd",
+    "In", "Sn", "Sb", "Te", "I", "Xe", "Cs", "Ba", "La", "Ce", "Pr", "Nd", "Pm", "Sm", "Eu", "Gd",
+    "Tb", "Dy", "Ho", "Er", "Tm", "Yb", "Lu", "Hf", "Ta", "W", "Re", "Os", "Ir", "Pt", "Au", "Hg",
+    "Tl", "Pb", "Bi", "Po", "At", "Rn", "Fr", "Ra", "Ac", "Th", "Pa", "U", "Np", "Pu", "Am", "Cm",
+    "Bk", "Cf", "Es", "Fm", "Md", "No", "Lr", "Rf", "Db", "Sg", "Bh", "Hs", "Mt", "Ds", "Rg", "Cn",
+    "Nh", "Fl", "Mc", "Lv", "Ts", "Og",
+];
+
+const SECONDS_PER_DAY: f64 = 86400.0;
+
+/// Length in seconds of a time unit, as written in ENSDF ("Y", "M", "MS", ...) or by users
+/// ("y", "min", "µs", ...).
+pub fn time_unit_in_seconds(unit: &str) -> Option<f64> {
+    let seconds = match unit.to_lowercase().as_str() {
+        "y" | "yr" => 365.2422 * SECONDS_PER_DAY,
+        "d" => SECONDS_PER_DAY,
+        "h" => 3600.0,
+        "m" | "min" => 60.0,
+        "s" => 1.0,
+        "ms" => 1e-3,
+        "us" | "µs" => 1e-6,
+        "ns" => 1e-9,
+        "ps" => 1e-12,
+        "fs" => 1e-15,
+        "as" => 1e-18,
+        _ => return None,
+    };
+    Some(seconds)
+}
+
+/// Half-life in the unit it was evaluated in, e.g. 30.08 ± 0.09 y.
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct HalfLife {
+    pub value: f64,
+    pub uncertainty: f64,
+    /// Time unit as written in ENSDF, see [`time_unit_in_seconds`].
+    pub unit: String,
+}
+
+impl HalfLife {
+    pub fn seconds(&self) -> f64 {
+        self.value * time_unit_in_seconds(&self.unit).unwrap_or(f64::NAN)
+    }
+}
+
+impl std::fmt::Display for HalfLife {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let unit = match self.unit.as_str() {
+            "M" => "min".to_string(),
+            "US" => "µs".to_string(),
+            u => u.to_lowercase(),
+        };
+        match self.uncertainty > 0.0 {
+            true => write!(f, "{} ± {} {unit}", self.value, self.uncertainty),
+            false => write!(f, "{} {unit}", self.value),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct Nuclide {
+    pub mass_number: u32,
+    pub atomic_number: u32,
+    /// Excitation energy in keV of the level, zero for the ground state.
+    pub level_energy: f64,
+}
+
+impl Nuclide {
+    /// Parses an ENSDF nuclide identifier such as "137CS" or "60NI".
+    pub fn from_nucid(nucid: &str) -> Option<Self> {
+        let nucid = nucid.trim();
+        let split = nucid.find(|c: char| !c.is_ascii_digit())?;
+        let (mass_number, symbol) = nucid.split_at(split);
+        let atomic_number = match symbol.to_uppercase().as_str() {
+            // ENSDF writes the neutron as "NN"
+            "NN" => 0,
+            s => ELEMENTS.iter().position(|e| e.to_uppercase() == s)?,
+        };
+        Some(Self {
+            mass_number: mass_number.parse().ok()?,
+            atomic_number: atomic_number as u32,
+            level_energy: 0.0,
+        })
+    }
+
+    pub fn symbol(&self) -> &'static str {
+        ELEMENTS.get(self.atomic_number as usize).unwrap_or(&"?")
+    }
+
+    /// Whether `other` is the same level of the same nuclide. Level energies may be rounded
+    /// differently from one dataset to another, so they only need to agree within 0.05 keV.
+    pub fn same_level(&self, other: &Nuclide) -> bool {
+        self.atomic_number == other.atomic_number
+            && self.mass_number == other.mass_number
+            && (self.level_energy - other.level_energy).abs() < 0.05
+    }
+}
+
+impl std::fmt::Display for Nuclide {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let isomer = if self.level_energy > 0.0 { "m" } else { "" };
+        write!(f, "{}{}{}", self.mass_number, isomer, self.symbol())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub enum DecayMode {
+    Alpha,
+    BetaMinus,
+    BetaPlus,
+    ElectronCapture,
+    IsomericTransition,
+    SpontaneousFission,
+    /// Any other mode, as written in the ENSDF dataset ID (e.g. "B-N").
+    Other(String),
+}
+
+impl DecayMode {
+    pub fn from_ensdf(mode: &str) -> Self {
+        match mode {
+            "A" => DecayMode::Alpha,
+            "B-" => DecayMode::BetaMinus,
+            "B+" => DecayMode::BetaPlus,
+            "EC" | "EC+B+" => DecayMode::ElectronCapture,
+            "IT" => DecayMode::IsomericTransition,
+            "SF" => DecayMode::SpontaneousFission,
+            other => DecayMode::Other(other.to_string()),
+        }
+    }
+}
+
+impl std::fmt::Display for DecayMode {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            DecayMode::Alpha => write!(f, "α"),
+            DecayMode::BetaMinus => write!(f, "β-"),
+            DecayMode::BetaPlus => write!(f, "β+"),
+            DecayMode::ElectronCapture => write!(f, "EC"),
+            DecayMode::IsomericTransition => write!(f, "IT"),
+            DecayMode::SpontaneousFission => write!(f, "SF"),
+            DecayMode::Other(m) => write!(f, "{m}"),
+        }
+    }
+}
+
+/// A decay as described by one ENSDF decay dataset.
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct Decay {
+    pub parent: Nuclide,
+    pub mode: DecayMode,
+    pub daughter: Nuclide,
+    /// ENSDF dataset ID, e.g. "137CS B- DECAY (30.08 Y)", used to label the decay.
+    pub dataset: String,
+    /// Half-life of the parent level, unknown for stable or unmeasured ones.
+    #[serde(default)]
+    pub half_life: Option<HalfLife>,
+    /// Fraction of the decays of the parent going through this dataset (BR of the
+    /// normalization record), unknown when not given.
+    #[serde(default)]
+    pub branching_ratio: Option<f64>,
+    /// Isomeric levels of the daughter fed by the decay, directly or through the gammas
+    /// de-exciting higher levels, as (level energy in keV, fraction of the decays ending in
+    /// it); the other decays end in the ground state.
+    #[serde(default)]
+    pub fed_isomers: Vec<(f64, f64)>,
+}
+
+impl Decay {
+    /// Levels of the daughter the decay ends in, ground state first, with the fraction of the
+    /// decays ending in each; levels never fed are left out.
+    pub fn fed_levels(&self) -> Vec<(Nuclide, f64)> {
+        let isomers: f64 = self.fed_isomers.iter().map(|(_, f)| f).sum();
+        let ground_state = Nuclide {
+            level_energy: 0.0,
+            ..self.daughter.clone()
+        };
+        std::iter::once((ground_state, (1.0 - isomers).max(0.0)))
+            .chain(self.fed_isomers.iter().map(|&(level_energy, f)| {
+                let level = Nuclide {
+                    level_energy,
+                    ..self.daughter.clone()
+                };
+                (level, f)
+            }))
+            .filter(|(_, f)| *f > 0.0)
+            .collect()
+    }
+}
+
+impl std::fmt::Display for Decay {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.dataset)
+    }
+}
@@ -0,0 +1,418 @@
+//! Peak search in measured spectra with the smoothed second-difference method of
+//! Mariscotti (NIM 50 (1967) 309): the spectrum is convolved with the negative second
+//! derivative of a Gaussian of the expected peak width, which removes a linear background,
+//! and peaks are the local maxima standing out of the statistical noise.
+//!
+//! Each peak found is then fitted with a Gaussian on a linear background to get its centroid,
+//! FWHM and net area with their uncertainties.
+
+use crate::fit::fit_least_squares;
+use crate::query_parser::{Energy, Modifier};
+use crate::resolution::FWHM_PER_SIGMA;
+use crate::spectrum::Spectrum;
+
+#[derive(Clone, PartialEq, serde::Deserialize, serde::Serialize)]
+#[serde(default)]
+pub struct PeakSearch {
+    /// Expected full width at half maximum of the peaks, in channels.
+    pub fwhm: f64,
+    /// Smallest significance of a peak, in standard deviations of the filtered spectrum.
+    pub threshold: f64,
+    /// Half width of the match window of fitted peaks, in standard uncertainties of their
+    /// energy.
+    pub sigmas: f64,
+}
+
+impl PeakSearch {
+    /// Rejects settings the peak search cannot work with.
+    pub fn check(&self) -> Result<(), String> {
+        match self.fwhm > 0.0 && self.fwhm.is_finite() {
+            true => Ok(()),
+            false => Err(format!(
+                "the peak FWHM must be a positive number of channels, not {}",
+                self.fwhm
+            )),
+        }
+    }
+}
+
+impl Default for PeakSearch {
+    fn default() -> Self {
+        Self {
+            fwhm: 5.0,
+            threshold: 4.0,
+            sigmas: 3.0,
+        }
+    }
+}
+
+/// Narrowest half width of a match window, in channels: the centroids of strong peaks are
+/// known far better than the energy calibration of most spectra.
+pub const MIN_WINDOW: f64 = 0.1;
+
+/// Gaussian on a linear background fitted to a peak; positions and widths in channels.
+#[derive(Clone)]
+pub struct PeakFit {
+    pub centroid: f64,
+    pub centroid_uncertainty: f64,
+    pub fwhm: f64,
+    pub fwhm_uncertainty: f64,
+    /// Counts in the peak above the background.
+    pub area: f64,
+    pub area_uncertainty: f64,
+    /// χ² per degree of freedom of the fit.
+    pub reduced_chi2: f64,
+}
+
+/// A peak found in a spectrum.
+#[derive(Clone)]
+pub struct Peak {
+    /// Position of the peak, in channels.
+    pub channel: f64,
+    /// Energy of the peak in keV, `None` for uncalibrated spectra.
+    pub energy: Option<f64>,
+    /// Standard uncertainty of `energy` in keV: those of the fitted centroid and of the
+    /// energy calibration, added in quadrature.
+    pub energy_uncertainty: Option<f64>,
+    /// Fitted FWHM of the peak in keV.
+    pub fwhm: Option<f64>,
+    /// Half width in keV of the match window of the peak: [`PeakSearch::sigmas`] standard
+    /// uncertainties of `energy`, or half the expected FWHM when the fit failed; at least
+    /// [`MIN_WINDOW`] channel either way.
+    pub window: Option<f64>,
+    /// Height of the filtered spectrum at the peak, in standard deviations.
+    pub significance: f64,
+    pub fit: Option<PeakFit>,
+}
+
+impl Peak {
+    /// Query energy matching the records within the window of the peak.
+    pub fn to_energy(&self) -> Option<Energy> {
+        let e = self.energy?;
+        let de = self.window.unwrap_or(0.0);
+        Some(Energy::new(e - de, e + de, Modifier::Definitely))
+    }
+
+    /// Query line giving the same window as [`Peak::to_energy`], e.g.
+    /// "661.657 keV ± 0.00412 keV 3.00σ # FWHM 1.85 ± 0.01 keV, area 19970 ± 150". The
+    /// uncertainty keeps three significant digits, however small.
+    pub fn query_line(&self) -> Option<String> {
+        let e = self.energy?;
+        let window = self.window.unwrap_or(0.0);
+        let (Some(fit), Some(de), Some(fwhm)) = (&self.fit, self.energy_uncertainty, self.fwhm)
+        else {
+            let d = decimals(window, 2);
+            return Some(format!(
+                "{e:.d$} keV ± {window:.d$} keV # channel {:.1}, {:.1}σ, not fitted",
+                self.channel, self.significance
+            ));
+        };
+        let d = decimals(de, 3);
+        let dfwhm = fwhm * fit.fwhm_uncertainty / fit.fwhm;
+        Some(format!(
+            "{e:.d$} keV ± {de:.d$} keV {:.2}σ # FWHM {fwhm:.2} ± {dfwhm:.2} keV, area {:.0} ± {:.0}",
+            window / de,
+            fit.area,
+            fit.area_uncertainty
+        ))
+    }
+}
+
+/// Decimals showing `x` with three significant digits, and at least `min`.
+fn decimals(x: f64, min: usize) -> usize {
+    match x > 0.0 && x.is_finite() {
+        true => (2.0 - x.log10().floor()).max(min as f64) as usize,
+        false => min,
+    }
+}
+
+/// Fits a Gaussian on a linear background to the counts of `spectrum` between the channels
+/// `range`, starting from a peak at `channel` with the given FWHM.
+fn fit_peak(
+    spectrum: &Spectrum,
+    range: (usize, usize),
+    channel: f64,
+    fwhm: f64,
+) -> Option<PeakFit> {
+    let (start, end) = range;
+    // At least one degree of freedom for the five parameters
+    if end < start + 6 {
+        return None;
+    }
+    let x: Vec<f64> = (start..end).map(|i| i as f64).collect();
+    let y = &spectrum.counts[start..end];
+    let w: Vec<f64> = y.iter().map(|y| 1.0 / y.max(1.0)).collect();
+    // Background through the edges of the region, the slope relative to the peak
+    let (first, last) = (y[0], y[y.len() - 1]);
+    let slope = (last - first) / (x[x.len() - 1] - x[0]);
+    let background = first + slope * (channel - x[0]);
+    let height = spectrum.counts[channel.round() as usize] - background;
+    let sigma = fwhm / FWHM_PER_SIGMA;
+    let model = |x: f64, p: &[f64]| {
+        let (a, mu, s, b0, b1) = (p[0], p[1], p[2], p[3], p[4]);
+        let u = (x - mu) / s;
+        let g = (-u * u / 2.0).exp();
+        let value = a * g + b0 + b1 * (x - channel);
+        (
+            value,
+            vec![g, a * g * u / s, a * g * u * u / s, 1.0, x - channel],
+        )
+    };
+    let fit = fit_least_squares(
+        &x,
+        y,
+        &w,
+        vec![height, channel, sigma, background, slope],
+        model,
+    )?;
+    let (a, mu, s) = (fit.params[0], fit.params[1], fit.params[2].abs());
+    // Noise and unresolved multiplets give widths far from the expected one
+    let plausible_width = (fwhm / 3.0..=fwhm * 3.0).contains(&(s * FWHM_PER_SIGMA));
+    if a <= 0.0
+        || !(x[0]..=x[x.len() - 1]).contains(&mu)
+        || !plausible_width
+        || !fit.uncertainty(1).is_finite()
+        || fit.uncertainty(1) >= fwhm
+    {
+        return None;
+    }
+    // Area a·s·sqrt(2π), with the covariance of a and s
+    let root = (2.0 * std::f64::consts::PI).sqrt();
+    let (da, ds) = (s * root, a * root);
+    let area_variance = da * da * fit.covariance[0][0]
+        + ds * ds * fit.covariance[2][2]
+        + 2.0 * da * ds * fit.covariance[0][2];
+    Some(PeakFit {
+        centroid: mu,
+        centroid_uncertainty: fit.uncertainty(1),
+        fwhm: s * FWHM_PER_SIGMA,
+        fwhm_uncertainty: fit.uncertainty(2) * FWHM_PER_SIGMA,
+        area: a * s * root,
+        area_uncertainty: area_variance.max(0.0).sqrt(),
+        reduced_chi2: fit.reduced_chi2,
+    })
+}
+
+/// Finds the peaks of `spectrum`, sorted by channel.
+pub fn find_peaks(spectrum: &Spectrum, settings: &PeakSearch) -> Vec<Peak> {
+    let sigma = (settings.fwhm / FWHM_PER_SIGMA).max(0.5);
+    let reach = (3.0 * sigma).ceil() as isize;
+    // Negative second derivative of a Gaussian, shifted to sum to zero so that constant
+    // and linear backgrounds give no response
+    let mut kernel: Vec<f64> = (-reach..=reach)
+        .map(|j| {
+            let x2 = (j * j) as f64 / (sigma * sigma);
+            (1.0 - x2) * (-x2 / 2.0).exp()
+        })
+        .collect();
+    let mean = kernel.iter().sum::<f64>() / kernel.len() as f64;
+    kernel.iter_mut().for_each(|c| *c -= mean);
+
+    let counts = &spectrum.counts;
+    let n = counts.len() as isize;
+    // Filtered spectrum and its significance, zero near the edges
+    let filtered: Vec<(f64, f64)> = (0..n)
+        .map(|i| {
+            if i < reach || i + reach >= n {
+                return (0.0, 0.0);
+            }
+            let (mut s, mut variance) = (0.0, 0.0);
+            for (k, c) in kernel.iter().enumerate() {
+                let y = counts[(i + k as isize - reach) as usize];
+                s += c * y;
+                variance += c * c * y.max(1.0);
+            }
+            (s, s / variance.sqrt())
+        })
+        .collect();
+
+    let half = ((settings.fwhm / 2.0).round() as usize).max(1);
+    let mut found: Vec<(f64, f64)> = vec![];
+    for i in 1..filtered.len().saturating_sub(1) {
+        let (s, z) = filtered[i];
+        if z < settings.threshold {
+            continue;
+        }
+        let neighbours = i.saturating_sub(half)..(i + half + 1).min(filtered.len());
+        if neighbours
+            .filter(|&j| j != i)
+            .any(|j| filtered[j].0 > s || (filtered[j].0 == s && j < i))
+        {
+            continue;
+        }
+        // Vertex of the parabola through the filtered spectrum around the maximum
+        let (left, right) = (filtered[i - 1].0, filtered[i + 1].0);
+        let curvature = left - 2.0 * s + right;
+        let offset = match curvature < 0.0 {
+            true => ((left - right) / (2.0 * curvature)).clamp(-0.5, 0.5),
+            false => 0.0,
+        };
+        found.push((i as f64 + offset, z));
+    }
+
+    // Fit regions of three FWHM on each side, stopping halfway to the neighbouring peaks
+    let reach = 3.0 * settings.fwhm;
+    let mut peaks = vec![];
+    for (k, &(channel, significance)) in found.iter().enumerate() {
+        let low = match k {
+            0 => channel - reach,
+            _ => (channel - reach).max((found[k - 1].0 + channel) / 2.0),
+        };
+        let high = match found.get(k + 1) {
+            None => channel + reach,
+            Some(next) => (channel + reach).min((channel + next.0) / 2.0),
+        };
+        let range = (
+            low.ceil().max(0.0) as usize,
+            (high.floor() as usize + 1).min(counts.len()),
+        );
+        let fit = fit_peak(spectrum, range, channel, settings.fwhm);
+        let position = fit.as_ref().map_or(channel, |f| f.centroid);
+        let kev_per_channel = spectrum.kev_per_channel(position);
+        let energy_uncertainty = fit
+            .as_ref()
+            .zip(kev_per_channel)
+            .map(|(f, k)| (f.centroid_uncertainty * k).hypot(spectrum.calibration_uncertainty));
+        peaks.push(Peak {
+            channel: position,
+            energy: spectrum.energy(position),
+            energy_uncertainty,
+            fwhm: fit.as_ref().zip(kev_per_channel).map(|(f, k)| f.fwhm * k),
+            window: kev_per_channel.map(|k| {
+                let window = match energy_uncertainty {
+                    Some(de) => settings.sigmas * de,
+                    None => k * settings.fwhm / 2.0,
+                };
+                window.max(MIN_WINDOW * k)
+            }),
+            significance,
+            fit,
+        });
+    }
+    peaks
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::assert_close;
+
+    /// Noise-free spectrum of 512 channels: Gaussian peaks (centroid, area, FWHM in channels)
+    /// on the background 100 + 0.1·ch, calibrated at 0.5 keV per channel.
+    fn spectrum(peaks: &[(f64, f64, f64)]) -> Spectrum {
+        let counts = (0..512)
+            .map(|ch| {
+                let x = ch as f64;
+                peaks.iter().fold(100.0 + 0.1 * x, |y, &(mu, area, fwhm)| {
+                    let s = fwhm / FWHM_PER_SIGMA;
+                    let u = (x - mu) / s;
+                    y + area / (s * (2.0 * std::f64::consts::PI).sqrt()) * (-u * u / 2.0).exp()
+                })
+            })
+            .collect();
+        Spectrum {
+            counts,
+            calibration: vec![0.0, 0.5],
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn linear_background_gives_no_peaks() {
+        assert!(find_peaks(&spectrum(&[]), &PeakSearch::default()).is_empty());
+    }
+
+    #[test]
+    fn find_separated_peaks() {
+        let peaks = find_peaks(
+            &spectrum(&[
+                (120.4, 5000.0, 5.0),
+                (300.0, 2000.0, 5.0),
+                (420.0, 5.0, 5.0),
+            ]),
+            &PeakSearch::default(),
+        );
+        // The last one is far below the noise of the background
+        assert_eq!(peaks.len(), 2);
+        assert!((peaks[0].channel - 120.4).abs() < 0.5);
+        assert!((peaks[1].channel - 300.0).abs() < 0.5);
+        assert!(peaks[0].significance > peaks[1].significance);
+        assert!(peaks[1].significance > PeakSearch::default().threshold);
+    }
+
+    #[test]
+    fn fit_gaussian_on_linear_background() {
+        let peaks = find_peaks(&spectrum(&[(120.4, 5000.0, 5.0)]), &PeakSearch::default());
+        let fit = peaks[0].fit.as_ref().unwrap();
+        assert!((fit.centroid - 120.4).abs() < 1e-4, "{}", fit.centroid);
+        assert!((fit.area - 5000.0).abs() < 0.5, "{}", fit.area);
+        assert!((fit.fwhm - 5.0).abs() < 1e-3, "{}", fit.fwhm);
+        // Poisson weights give an uncertainty of about sqrt(5000 + background) counts
+        assert!((60.0..110.0).contains(&fit.area_uncertainty));
+        assert!(fit.reduced_chi2 < 1e-6);
+        assert_eq!(peaks[0].energy, Some(fit.centroid * 0.5));
+        assert_eq!(peaks[0].fwhm, Some(fit.fwhm * 0.5));
+    }
+
+    #[test]
+    fn windows_add_the_calibration_uncertainty() {
+        let mut sample = spectrum(&[(120.4, 5000.0, 5.0)]);
+        let settings = PeakSearch::default();
+        let fitted = find_peaks(&sample, &settings)[0].clone();
+        let de = fitted.energy_uncertainty.unwrap();
+        assert_eq!(fitted.window, Some(3.0 * de));
+        sample.calibration_uncertainty = 0.1;
+        let peak = find_peaks(&sample, &settings)[0].clone();
+        let expected = de.hypot(0.1);
+        assert_close(peak.energy_uncertainty.unwrap(), expected);
+        assert_eq!(peak.window, Some(3.0 * expected));
+        // Never narrower than a tenth of a channel
+        let narrow = PeakSearch {
+            sigmas: 0.5,
+            ..settings
+        };
+        let peak = find_peaks(&spectrum(&[(120.4, 1e6, 5.0)]), &narrow)[0].clone();
+        assert_eq!(peak.window, Some(MIN_WINDOW * 0.5));
+    }
+
+    #[test]
+    fn query_line_gives_the_window() {
+        let resolution = crate::resolution::ResolutionModel::None;
+        for area in [5000.0, 1e6] {
+            let peak =
+                find_peaks(&spectrum(&[(120.4, area, 5.0)]), &PeakSearch::default())[0].clone();
+            let line = peak.query_line().unwrap();
+            let parsed = &crate::query_parser::parse_query(&line, &resolution)
+                .unwrap_or_else(|e| panic!("{line}: {e}"))
+                .energies[0];
+            let energy = peak.to_energy().unwrap();
+            let tolerance = 0.01 * (energy.uteb - energy.lteb);
+            assert!((parsed.lteb - energy.lteb).abs() < tolerance, "{line}");
+            assert!((parsed.uteb - energy.uteb).abs() < tolerance, "{line}");
+        }
+    }
+
+    #[test]
+    fn narrow_peak_settings() {
+        let sample = spectrum(&[(120.4, 5000.0, 5.0)]);
+        assert!(fit_peak(&sample, (118, 123), 120.4, 5.0).is_none());
+        assert!(fit_peak(&sample, (120, 120), 120.4, 5.0).is_none());
+        for fwhm in [0.05, 1e-9] {
+            let settings = PeakSearch {
+                fwhm,
+                ..Default::default()
+            };
+            assert!(settings.check().is_ok());
+            for peak in find_peaks(&sample, &settings) {
+                assert!(peak.fit.is_none());
+            }
+        }
+        for fwhm in [0.0, -1.0, f64::NAN, f64::INFINITY] {
+            let settings = PeakSearch {
+                fwhm,
+                ..Default::default()
+            };
+            assert!(settings.check().is_err(), "{fwhm}");
+        }
+    }
+}
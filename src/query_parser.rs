@@ -1,18 +1,31 @@
 use crate::app::{PrintMode, RadiationType};
-use crate::database::TransitionResult;
-use log::{debug, error};
+use crate::export::{results_to_csv, results_to_json, OutputFormat};
+use crate::nuclide::time_unit_in_seconds;
+use crate::resolution::ResolutionModel;
+use crate::search::{search, HalfLifeFilter, SearchError, SearchOptions, SearchResult};
+use log::debug;
 use once_cell::sync::Lazy;
 use regex::Regex;
-use std::collections::HashMap;
+use std::ops::Range;
 
-use crate::database::query_database;
-
-//const QUERY_PATTERN: &str = r"^(?P<modifier>[a-zA-Z]*)?(\s*)?(?P<energy>([0-9]*[.])?[0-9]+){1}(\s*)?((?P<unit>[a-zA-Z]*)\s?){1}(\s*)?((?P<uncertainty>([0-9]*[.])?[0-9]+)%)?$";
-//const QUERY_PATTERN: &str = r"^(?P<modifier>[a-zA-Z]*)?(\s*)?(?P<energy>([0-9]*[.])?[0-9]+)(\s*)?((?P<unit>[a-zA-Z]*)\s?)\s+((?P<uncertainty>([0-9]*[.])?[0-9]+)%)?\s*";
-const QUERY_PATTERN: &str = r"^(?P<modifier>[a-zA-Z]*)?[[:blank:]]?(?P<energy>([0-9]*[.])?[0-9]+)[[:blank:]]?(?P<unit>[a-zA-Z]+)([[:blank:]]+(?P<uncertainty>([0-9]*[.])?[0-9]+)%)?";
+// A query line is an optional modifier, an energy with its unit and an optional uncertainty.
+// The uncertainty is either a percentage (661.7 keV 1%), an absolute value (661.7 keV ± 0.3 keV,
+// also written +/- or +-) or given in units of the last digits (661.7(3) keV); it can be followed
+// by a multiplier such as 2σ (or 2sigma).
+// Intensity constraints are written I>10% (per 100 decays) or I/L1>0.5 (relative to the line
+// matching line 1 of the query), with <, <=, > or >=. Query lines are numbered as in the editor,
+// comments, blank lines and T1/2 lines included, here as in the results and the diagnostics.
+// Lines starting with T1/2 (e.g. T1/2 > 1 h) are not energies but bounds on the half-life of
+// the parent.
+// Lines are split into tokens first, so that errors can point at the offending part of the line.
+const TOKEN_PATTERN: &str = r"(?P<number>[0-9.]+(\([^)\s]*\)?)?)|(?P<word>\p{L}+)|(?P<plus_minus>±|\+/-|\+-)|(?P<percent>%)|(?P<comparison>[<>]=?)|(?P<other>\S)";
 
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub enum Modifier {
+    /// The line must be explained by a candidate.
     Definitely,
+    /// Optional line: it raises the score of the candidates explaining it,
+    /// but does not count against the others.
     Maybe,
 }
 
@@ -29,10 +42,65 @@ impl std::fmt::Display for Modifier {
     }
 }
 
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Comparison {
+    /// `>`
+    Above,
+    /// `>=`
+    AtLeast,
+    /// `<`
+    Below,
+    /// `<=`
+    AtMost,
+}
+
+/// Bound on the intensity of the record matching a query line.
+#[derive(Debug, Clone, PartialEq)]
+pub struct IntensityConstraint {
+    /// Index (into the query energies) of the line the intensity is relative to,
+    /// `None` for an intensity per 100 decays.
+    pub reference: Option<usize>,
+    pub comparison: Comparison,
+    pub value: f64,
+}
+
+impl IntensityConstraint {
+    pub fn accepts(&self, intensity: f64) -> bool {
+        match self.comparison {
+            Comparison::Above => intensity > self.value,
+            Comparison::AtLeast => intensity >= self.value,
+            Comparison::Below => intensity < self.value,
+            Comparison::AtMost => intensity <= self.value,
+        }
+    }
+}
+
+#[derive(Clone)]
 pub struct Energy {
     pub lteb: f64,
     pub uteb: f64,
     pub modifier: Modifier,
+    /// Radiation type written before the energy (e.g. "alpha 5.304 MeV"); lines without one
+    /// take the type of the search.
+    pub radiation_type: Option<RadiationType>,
+    pub intensity: Vec<IntensityConstraint>,
+    /// Line of the query the energy was written on, starting at 1 and counted as in the
+    /// editor; `None` for energies not read from a query.
+    pub line: Option<usize>,
+}
+
+impl Energy {
+    /// Energy window between `lteb` and `uteb`, both in keV.
+    pub fn new(lteb: f64, uteb: f64, modifier: Modifier) -> Self {
+        Self {
+            lteb,
+            uteb,
+            modifier,
+            radiation_type: None,
+            intensity: Vec::new(),
+            line: None,
+        }
+    }
 }
 
 impl std::fmt::Display for Energy {
@@ -41,126 +109,630 @@ impl std::fmt::Display for Energy {
             f,
             "lower bound={}; upper bound={};modifier: {}",
             self.lteb, self.uteb, self.modifier
+        )?;
+        if let Some(t) = self.radiation_type {
+            write!(f, "; type: {t}")?;
+        }
+        Ok(())
+    }
+}
+
+/// A problem found in a line of the search query.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Diagnostic {
+    /// Line of the query, starting at 1.
+    pub line: usize,
+    /// Column where the problem starts, in characters and starting at 1.
+    pub column: usize,
+    /// Byte range of the offending text within the line.
+    pub span: Range<usize>,
+    pub message: String,
+}
+
+impl std::fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "line {}, column {}: {}",
+            self.line, self.column, self.message
         )
     }
 }
 
-pub struct InputError;
+/// Location and message of a problem within a line, before it is attached to a line number.
+type LineError = (Range<usize>, String);
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum TokenKind {
+    Number,
+    Word,
+    PlusMinus,
+    Percent,
+    Comparison,
+    Other,
+}
+
+struct Token<'a> {
+    kind: TokenKind,
+    text: &'a str,
+    span: Range<usize>,
+}
+
+impl Token<'_> {
+    fn is_sigma(&self) -> bool {
+        self.kind == TokenKind::Word
+            && (self.text == "σ" || self.text.eq_ignore_ascii_case("sigma"))
+    }
+
+    fn unexpected(&self, expected: &str) -> LineError {
+        (
+            self.span.clone(),
+            format!("expected {expected}, found '{}'", self.text),
+        )
+    }
+}
+
+fn tokenize(line: &str) -> Vec<Token<'_>> {
+    static RE: Lazy<Regex> = Lazy::new(|| Regex::new(TOKEN_PATTERN).unwrap());
+    RE.captures_iter(line)
+        .map(|cap| {
+            let (kind, m) = [
+                (TokenKind::Number, "number"),
+                (TokenKind::Word, "word"),
+                (TokenKind::PlusMinus, "plus_minus"),
+                (TokenKind::Percent, "percent"),
+                (TokenKind::Comparison, "comparison"),
+                (TokenKind::Other, "other"),
+            ]
+            .into_iter()
+            .find_map(|(kind, name)| cap.name(name).map(|m| (kind, m)))
+            .unwrap();
+            Token {
+                kind,
+                text: m.as_str(),
+                span: m.range(),
+            }
+        })
+        .collect()
+}
+
+/// Parses a plain number such as "661.7".
+fn parse_number(token: &Token<'_>) -> Result<f64, LineError> {
+    match token.text.parse::<f64>() {
+        Ok(v) if !token.text.contains('(') => Ok(v),
+        _ => Err((
+            token.span.clone(),
+            format!("'{}' is not a number", token.text),
+        )),
+    }
+}
 
-fn calculate_energy_bounds(energy: &str, uncertainty: &str, unit: &str) -> (f64, f64) {
-    let e = energy.parse::<f64>().unwrap();
-    let u: f64 = if !uncertainty.is_empty() {
-        uncertainty.parse::<f64>().unwrap() / 100.0
-    } else {
-        0.0
+/// Parses an energy, possibly followed by an uncertainty in units of its last digits
+/// (e.g. "661.7(3)"), and returns both in the units they are written in.
+fn parse_energy_value(token: &Token<'_>) -> Result<(f64, Option<f64>), LineError> {
+    let (value, digits) = match token.text.split_once('(') {
+        Some((value, digits)) => (value, Some(digits)),
+        None => (token.text, None),
     };
+    let Ok(e) = value.parse::<f64>() else {
+        let span = token.span.start..token.span.start + value.len();
+        return Err((span, format!("'{value}' is not a number")));
+    };
+    let Some(digits) = digits else {
+        return Ok((e, None));
+    };
+    let span = token.span.start + value.len()..token.span.end;
+    match digits.strip_suffix(')') {
+        Some(d) if !d.is_empty() && d.chars().all(|c| c.is_ascii_digit()) => {
+            let decimals = value.split_once('.').map_or(0, |(_, d)| d.len()) as i32;
+            Ok((e, Some(d.parse::<f64>().unwrap() * 10f64.powi(-decimals))))
+        }
+        _ => Err((
+            span,
+            format!(
+                "bad uncertainty '({digits}', expected the last digits in parentheses as in 661.7(3)"
+            ),
+        )),
+    }
+}
+
+/// Energy of one unit in keV. Case does not matter but for the prefix M, which is not m
+/// (meV would be a millielectronvolt).
+fn unit_in_kev(token: &Token<'_>) -> Result<f64, LineError> {
+    let unit = token.text;
+    let prefix = unit
+        .len()
+        .checked_sub(2)
+        .filter(|&n| unit.get(n..).is_some_and(|s| s.eq_ignore_ascii_case("ev")))
+        .map(|n| &unit[..n]);
+    match prefix {
+        Some("M") => Ok(1e3),
+        Some("k" | "K") => Ok(1.0),
+        Some("") => Ok(1e-3),
+        _ => Err((
+            token.span.clone(),
+            format!("unknown unit '{unit}', expected eV, keV or MeV"),
+        )),
+    }
+}
+
+/// Uncertainty of a query line as written by the user.
+enum Uncertainty {
+    None,
+    /// Percentage of the energy.
+    Relative(f64),
+    /// Value in keV.
+    Absolute(f64),
+}
 
-    let base: f64 = 10.0;
-    let m = match unit {
-        "MeV" => 3.0,
-        "keV" => 0.0,
-        "eV" => -3.0,
-        _ => 0.0,
+/// Lines without an uncertainty get their window from the detector `resolution`.
+fn calculate_energy_bounds(
+    e: f64,
+    uncertainty: Uncertainty,
+    sigma: Option<f64>,
+    resolution: &ResolutionModel,
+) -> (f64, f64) {
+    let k = sigma.unwrap_or(1.0);
+    let u = match uncertainty {
+        Uncertainty::None => {
+            let half_window = resolution.half_window(e, sigma);
+            return (e - half_window, e + half_window);
+        }
+        Uncertainty::Relative(p) => e * p / 100.0,
+        Uncertainty::Absolute(a) => a,
     };
 
-    let lteb = e * base.powf(m) * (1.0 - u);
-    let uteb = e * base.powf(m) * (1.0 + u);
+    let lteb = e - k * u;
+    let uteb = e + k * u;
     (lteb, uteb)
 }
 
-fn parse_line(line: &str) -> Result<Energy, InputError> {
-    static RE: Lazy<Regex> = Lazy::new(|| Regex::new(QUERY_PATTERN).unwrap());
-    match RE.captures(line) {
+/// Parses an intensity constraint following its "I", e.g. ">10%" or "/L1>0.5", on the query
+/// line `number`. `energy_lines` are the numbers of the lines of the query with an energy.
+fn parse_intensity<'a, I: Iterator<Item = &'a Token<'a>>>(
+    i: &Token<'_>,
+    tokens: &mut std::iter::Peekable<I>,
+    number: usize,
+    energy_lines: &[usize],
+) -> Result<IntensityConstraint, LineError> {
+    let reference = match tokens.next_if(|t| t.text == "/") {
+        None => None,
+        Some(slash) => {
+            let l =
+                tokens.next_if(|t| t.kind == TokenKind::Word && t.text.eq_ignore_ascii_case("l"));
+            let n = l.and_then(|_| tokens.next_if(|t| t.kind == TokenKind::Number));
+            let Some(n) = n else {
+                return Err((
+                    slash.span.clone(),
+                    "expected a query line such as L1 after 'I/'".to_string(),
+                ));
+            };
+            let k = n.text.parse::<usize>().ok();
+            match k.and_then(|k| energy_lines.iter().position(|l| *l == k)) {
+                _ if k == Some(number) => {
+                    return Err((
+                        n.span.clone(),
+                        "a line cannot be compared with itself".to_string(),
+                    ))
+                }
+                Some(index) => Some(index),
+                None => {
+                    return Err((
+                        n.span.clone(),
+                        format!("there is no energy on query line {}", n.text),
+                    ))
+                }
+            }
+        }
+    };
+    let comparison = match tokens.next() {
+        Some(c) if c.kind == TokenKind::Comparison => match c.text {
+            ">" => Comparison::Above,
+            ">=" => Comparison::AtLeast,
+            "<" => Comparison::Below,
+            _ => Comparison::AtMost,
+        },
+        Some(c) => return Err(c.unexpected("<, <=, > or >=")),
         None => {
-            debug!("NO MATCH: {line}");
-            Err(InputError)
+            return Err((
+                i.span.end..i.span.end,
+                "missing comparison after 'I'".to_string(),
+            ))
         }
-        Some(cap) => {
-            let uncert = match cap.name("uncertainty") {
-                Some(m) => m.as_str(),
-                None => "",
-            };
-            //debug!(
-            //    "\n<{line}>\n{}",
-            //    format!(
-            //        "modifier: <{}>; energy: <{}>; unit: <{}>, uncertainty: <{}>\n",
-            //        &cap["modifier"], &cap["energy"], &cap["unit"], uncert,
-            //    )
-            //);
-            let (lteb, uteb) = calculate_energy_bounds(&cap["energy"], uncert, &cap["unit"]);
-            let modifier = match cap["modifier"].to_lowercase().as_ref() {
-                "definitely" => Modifier::Definitely,
-                "" => Modifier::Definitely,
-                "maybe" => Modifier::Maybe,
-                _ => Modifier::Definitely,
-            };
+    };
+    let value = match tokens.next() {
+        Some(v) if v.kind == TokenKind::Number => parse_number(v)?,
+        Some(v) => return Err(v.unexpected("an intensity")),
+        None => {
+            return Err((
+                i.span.clone(),
+                "missing intensity after the comparison".to_string(),
+            ))
+        }
+    };
+    // Intensities per 100 decays may be written with a % sign
+    if reference.is_none() {
+        tokens.next_if(|t| t.kind == TokenKind::Percent);
+    }
+    Ok(IntensityConstraint {
+        reference,
+        comparison,
+        value,
+    })
+}
 
-            let e = Energy {
-                lteb,
-                uteb,
-                modifier,
-            };
+/// Parses one line of the query, without its comment; the spans of the errors are byte ranges
+/// within `line`. `number` is the line of the query it was written on and `energy_lines` the
+/// numbers of the lines with an energy.
+fn parse_line(
+    line: &str,
+    number: usize,
+    energy_lines: &[usize],
+    resolution: &ResolutionModel,
+) -> Result<Energy, LineError> {
+    let tokens = tokenize(line);
+    let end = line.trim_end().len();
+    let missing = |what: &str| (end..end, format!("missing {what}"));
+    let mut tokens = tokens.iter().peekable();
+
+    // The modifier and the radiation type can be written in any order
+    let mut modifier = None;
+    let mut radiation_type = None;
+    while let Some(t) = tokens.next_if(|t| t.kind == TokenKind::Word) {
+        match (
+            t.text.to_lowercase().as_str(),
+            t.text.parse::<RadiationType>(),
+        ) {
+            ("definitely", _) if modifier.is_none() => modifier = Some(Modifier::Definitely),
+            ("maybe", _) if modifier.is_none() => modifier = Some(Modifier::Maybe),
+            (_, Ok(r)) if radiation_type.is_none() => radiation_type = Some(r),
+            _ => {
+                return Err((
+                    t.span.clone(),
+                    format!(
+                        "unexpected word '{}', expected maybe, definitely or a radiation type \
+                         (gamma, alpha, beta, xray, ce, auger) before the energy",
+                        t.text
+                    ),
+                ))
+            }
+        }
+    }
 
-            //debug!("{e}");
+    let (energy, last_digits) = match tokens.next() {
+        Some(t) if t.kind == TokenKind::Number => parse_energy_value(t)?,
+        Some(t) => return Err(t.unexpected("an energy")),
+        None => return Err(missing("energy")),
+    };
+    let unit = match tokens.next() {
+        Some(t) if t.kind == TokenKind::Word => unit_in_kev(t)?,
+        Some(t) => return Err(t.unexpected("a unit (eV, keV or MeV)")),
+        None => return Err(missing("unit after the energy (eV, keV or MeV)")),
+    };
+    let mut uncertainty = match last_digits {
+        Some(u) => Uncertainty::Absolute(u * unit),
+        None => Uncertainty::None,
+    };
 
-            Ok(e)
+    let mut sigma = None;
+    let mut intensity = Vec::new();
+    while let Some(t) = tokens.next() {
+        let already_given = |t: &Token<'_>| {
+            (
+                t.span.clone(),
+                "the uncertainty is given more than once".to_string(),
+            )
+        };
+        match t.kind {
+            TokenKind::PlusMinus => {
+                if !matches!(uncertainty, Uncertainty::None) || sigma.is_some() {
+                    return Err(already_given(t));
+                }
+                let value = match tokens.next() {
+                    Some(v) if v.kind == TokenKind::Number => parse_number(v)?,
+                    Some(v) => return Err(v.unexpected(&format!("a value after '{}'", t.text))),
+                    None => return Err(missing(&format!("value after '{}'", t.text))),
+                };
+                // The unit of the uncertainty defaults to the unit of the energy
+                let value_unit =
+                    match tokens.next_if(|u| u.kind == TokenKind::Word && !u.is_sigma()) {
+                        Some(u) => unit_in_kev(u)?,
+                        None => unit,
+                    };
+                uncertainty = Uncertainty::Absolute(value * value_unit);
+            }
+            TokenKind::Number => match tokens.next() {
+                Some(p) if p.kind == TokenKind::Percent => {
+                    let percentage = p.span.end;
+                    let bad_percentage = (
+                        t.span.start..percentage,
+                        format!("bad percentage '{}'", &line[t.span.start..percentage]),
+                    );
+                    if !matches!(uncertainty, Uncertainty::None) || sigma.is_some() {
+                        return Err(already_given(t));
+                    }
+                    match parse_number(t) {
+                        Ok(p) if p <= 100.0 => uncertainty = Uncertainty::Relative(p),
+                        _ => return Err(bad_percentage),
+                    }
+                }
+                Some(s) if s.is_sigma() => {
+                    if sigma.is_some() {
+                        return Err((
+                            t.span.clone(),
+                            "the multiplier is given more than once".to_string(),
+                        ));
+                    }
+                    sigma = Some(parse_number(t)?);
+                }
+                Some(s) => return Err(s.unexpected(&format!("% or σ after '{}'", t.text))),
+                None => return Err(missing(&format!("% or σ after '{}'", t.text))),
+            },
+            TokenKind::Word if t.text.eq_ignore_ascii_case("i") => {
+                intensity.push(parse_intensity(t, &mut tokens, number, energy_lines)?);
+            }
+            _ => return Err((t.span.clone(), format!("unexpected '{}'", t.text))),
         }
     }
+
+    let (lteb, uteb) = calculate_energy_bounds(energy * unit, uncertainty, sigma, resolution);
+    Ok(Energy {
+        lteb,
+        uteb,
+        modifier: modifier.unwrap_or(Modifier::Definitely),
+        radiation_type,
+        intensity,
+        line: Some(number),
+    })
+}
+
+/// Parses a duration such as "1 h", "30min" or "2.5 y" into seconds.
+pub fn parse_duration(input: &str) -> Result<f64, String> {
+    let tokens = tokenize(input);
+    let [value, unit] = &tokens[..] else {
+        return Err(format!("expected a duration such as 1 h, found '{input}'"));
+    };
+    let value = parse_number(value).map_err(|(_, message)| message)?;
+    let unit_in_seconds = time_unit_in_seconds(unit.text).ok_or_else(|| {
+        format!(
+            "unknown time unit '{}', expected s, min, h, d or y",
+            unit.text
+        )
+    })?;
+    Ok(value * unit_in_seconds)
+}
+
+const HALF_LIFE_PREFIX: &str = "t1/2";
+
+fn is_half_life_line(line: &str) -> bool {
+    line.trim_start()
+        .to_lowercase()
+        .starts_with(HALF_LIFE_PREFIX)
+}
+
+/// Parses a bound on the half-life of the parent, e.g. "T1/2 > 1 h".
+fn parse_half_life_line(line: &str) -> Result<HalfLifeFilter, LineError> {
+    let prefix_end = line.len() - line.trim_start().len() + HALF_LIFE_PREFIX.len();
+    let tokens = tokenize(line);
+    let mut tokens = tokens.iter().skip_while(|t| t.span.start < prefix_end);
+    let end = line.trim_end().len();
+    let missing = |what: &str| (end..end, format!("missing {what}"));
+
+    let comparison = match tokens.next() {
+        Some(c) if c.kind == TokenKind::Comparison => c,
+        Some(c) => return Err(c.unexpected("<, <=, > or >= after T1/2")),
+        None => return Err(missing("comparison after T1/2")),
+    };
+    let value = match tokens.next() {
+        Some(v) if v.kind == TokenKind::Number => parse_number(v)?,
+        Some(v) => return Err(v.unexpected("a half-life")),
+        None => return Err(missing("half-life")),
+    };
+    let unit = match tokens.next() {
+        Some(u) if u.kind == TokenKind::Word => time_unit_in_seconds(u.text).ok_or((
+            u.span.clone(),
+            format!("unknown time unit '{}', expected s, min, h, d or y", u.text),
+        ))?,
+        Some(u) => return Err(u.unexpected("a time unit (s, min, h, d or y)")),
+        None => return Err(missing("time unit (s, min, h, d or y)")),
+    };
+    if let Some(t) = tokens.next() {
+        return Err((t.span.clone(), format!("unexpected '{}'", t.text)));
+    }
+
+    let seconds = Some(value * unit);
+    Ok(match comparison.text.starts_with('>') {
+        true => HalfLifeFilter {
+            min: seconds,
+            max: None,
+        },
+        false => HalfLifeFilter {
+            min: None,
+            max: seconds,
+        },
+    })
+}
+
+/// A parsed search query: the energies to look for and the filters written along them.
+pub struct Query {
+    pub energies: Vec<Energy>,
+    /// Bounds from lines such as "T1/2 > 1 h".
+    pub half_life: HalfLifeFilter,
 }
 
-fn parse_user_query(input: String) -> Result<Vec<Energy>, InputError> {
-    let lines = input.split('\n');
-    let mut energies = Vec::new();
+impl Query {
+    /// Runs [`search`] on the energies of the query, with its filters added to `options`.
+    pub fn search(
+        &self,
+        radiation_type: &RadiationType,
+        options: &SearchOptions,
+    ) -> Result<SearchResult, SearchError> {
+        let options = SearchOptions {
+            half_life: options.half_life.and(&self.half_life),
+            ..options.clone()
+        };
+        search(&self.energies, radiation_type, &options)
+    }
+}
+
+/// Parses a search query, one energy or filter per line, ignoring comments and blank lines.
+/// An uncertainty written on a line takes precedence over the detector `resolution`.
+/// Every malformed line is reported, not only the first one.
+pub fn parse_query(input: &str, resolution: &ResolutionModel) -> Result<Query, SearchError> {
+    let lines: Vec<(usize, &str)> = input
+        .split('\n')
+        .map(|line| line.split('#').next().unwrap())
+        .enumerate()
+        .filter(|(_, line)| !line.trim().is_empty())
+        .collect();
+    let (filters, lines): (Vec<_>, Vec<_>) = lines
+        .into_iter()
+        .partition(|(_, line)| is_half_life_line(line));
+    let mut query = Query {
+        energies: Vec::new(),
+        half_life: HalfLifeFilter::default(),
+    };
+    let mut errors = Vec::new();
 
-    for line in lines {
-        let line = line.split('#').next().unwrap().trim();
-        if line.is_empty() {
-            continue;
+    for &(i, line) in &filters {
+        match parse_half_life_line(line) {
+            Ok(filter) => query.half_life = query.half_life.and(&filter),
+            Err(e) => errors.push((i, line, e)),
         }
-        match parse_line(line) {
-            Ok(energy) => energies.push(energy),
-            Err(e) => {
-                error!("Error while parsing line: {line}");
-                return Err(e);
-            }
+    }
+    let energy_lines: Vec<usize> = lines.iter().map(|(i, _)| i + 1).collect();
+    for &(i, line) in &lines {
+        match parse_line(line, i + 1, &energy_lines, resolution) {
+            Ok(energy) => query.energies.push(energy),
+            Err(e) => errors.push((i, line, e)),
         }
     }
-    Ok(energies)
+    if !errors.is_empty() {
+        errors.sort_by_key(|(i, _, _)| *i);
+        let diagnostics = errors
+            .into_iter()
+            .map(|(i, line, (span, message))| {
+                debug!("Error while parsing line {}: {line}", i + 1);
+                Diagnostic {
+                    line: i + 1,
+                    column: line[..span.start].chars().count() + 1,
+                    span,
+                    message,
+                }
+            })
+            .collect();
+        return Err(SearchError::InvalidQuery(diagnostics));
+    }
+    Ok(query)
 }
 
-fn print_results(
-    energies: HashMap<String, Vec<TransitionResult>>,
-    print_mode: &PrintMode,
-) -> String {
+/// Formats indexes into the query energies as the numbers of their `query_lines`, e.g. "1, 3".
+pub fn line_numbers(query_lines: &[usize], lines: &[usize]) -> String {
+    lines
+        .iter()
+        .map(|i| query_lines[*i].to_string())
+        .collect::<Vec<String>>()
+        .join(", ")
+}
+
+/// Formats background query lines with the peak explaining them, e.g. "2 (K-40), 5 (Bi-214)".
+pub fn background_lines(query_lines: &[usize], lines: &[(usize, String)]) -> String {
+    lines
+        .iter()
+        .map(|(i, name)| format!("{} ({name})", query_lines[*i]))
+        .collect::<Vec<String>>()
+        .join(", ")
+}
+
+fn print_results(results: SearchResult, print_mode: &PrintMode) -> String {
     let mut ans = String::new();
     // Summarize findings
-    let noun = match energies.len() {
+    let noun = match results.candidates.len() {
         1 => "decay",
         _ => "decays",
     };
     ans += format!(
         "{} {} found (energies are given in keV, * denotes a match):\n",
-        energies.len(),
+        results.candidates.len(),
         noun
     )
     .as_str();
-    for (d, list) in energies.into_iter() {
+    if !results.unmatched_lines.is_empty() {
+        ans += format!(
+            "Query lines not explained by any decay: {}\n",
+            line_numbers(&results.query_lines, &results.unmatched_lines)
+        )
+        .as_str();
+    }
+    if !results.background_lines.is_empty() {
+        ans += format!(
+            "Query lines explained by background: {}\n",
+            background_lines(&results.query_lines, &results.background_lines)
+        )
+        .as_str();
+    }
+    for c in results.candidates {
         // print header for the trasition
-        ans += format!("\n{d}\n").as_str();
+        ans += format!("\n{} (score {:.2}, ", c.decay, c.score).as_str();
+        if let Some(half_life) = &c.decay.half_life {
+            ans += format!("T1/2 {half_life}, ").as_str();
+        }
+        ans += format!(
+            "explains query lines {}",
+            line_numbers(&results.query_lines, &c.matched_lines)
+        )
+        .as_str();
+        if !c.optional_lines.is_empty() {
+            ans += format!(
+                "; optional: {}",
+                line_numbers(&results.query_lines, &c.optional_lines)
+            )
+            .as_str();
+        }
+        if !c.chain.is_empty() {
+            let members: Vec<String> = c.chain.iter().map(|n| n.to_string()).collect();
+            ans += format!("; chain: {}", members.join(", ")).as_str();
+        }
+        if let Some(precursor) = &c.grown_in_from {
+            ans += format!("; grown in from {precursor}").as_str();
+        }
+        if !c.intensity_mismatches.is_empty() {
+            ans += format!(
+                "; intensity ratio not met: {}",
+                line_numbers(&results.query_lines, &c.intensity_mismatches)
+            )
+            .as_str();
+        }
+        ans += ")\n";
+        // Label the records with their radiation type when the query mixes several, and with
+        // their parent in grouped chains
+        let mixed = c
+            .transitions
+            .iter()
+            .any(|r| r.t.radiation_type != c.transitions[0].t.radiation_type);
+        let grouped = !c.chain.is_empty();
         let mut i = 1;
         // print each record inside the transition
-        for r in list {
+        for r in c.transitions {
             if *print_mode == PrintMode::OnlyMatches && !r.found {
                 // Omit not matching records for this printing mode
                 continue;
             }
+            let mut label = match mixed {
+                true => format!(" {:<6}", r.t.radiation_type),
+                false => String::new(),
+            };
+            if grouped {
+                label += format!(" {:<6}", r.t.decay.parent.to_string()).as_str();
+            }
             ans += format!(
-                "{}{:>5}{}\n",
+                "{}{:>5}{}{}\n",
                 match r.found {
                     true => "*",
                     false => " ",
                 },
                 i,
+                label,
                 r.t
             )
             .as_str();
@@ -173,17 +745,185 @@ fn print_results(
 pub fn search_energies(
     input: String,
     radiation_type: &RadiationType,
+    resolution: &ResolutionModel,
+    options: &SearchOptions,
     print_mode: &PrintMode,
+    output_format: &OutputFormat,
 ) -> String {
-    let energies = match parse_user_query(input) {
-        Ok(l) => l,
-        Err(_) => {
-            return "Verify the search query".to_string();
+    let results =
+        match parse_query(&input, resolution).and_then(|q| q.search(radiation_type, options)) {
+            Ok(r) => r,
+            Err(e) => {
+                return e.to_string();
+            }
+        };
+
+    format_results(results, print_mode, output_format)
+        .unwrap_or_else(|e| format!("Error while serializing the results: {e}"))
+}
+
+/// Renders the results of [`search`] as text, JSON or CSV.
+pub fn format_results(
+    results: SearchResult,
+    print_mode: &PrintMode,
+    output_format: &OutputFormat,
+) -> Result<String, String> {
+    match output_format {
+        OutputFormat::Text => match results.candidates.is_empty() {
+            false => Ok(print_results(results, print_mode)),
+            true => Ok("No results found".to_string()),
+        },
+        OutputFormat::Json => results_to_json(&results, print_mode),
+        OutputFormat::Csv => results_to_csv(&results, print_mode),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::assert_close;
+
+    fn energies(input: &str) -> Vec<Energy> {
+        match parse_query(input, &ResolutionModel::None) {
+            Ok(query) => query.energies,
+            Err(e) => panic!("{e}"),
         }
-    };
+    }
+
+    /// (line, column, message) of the problems found in `input`.
+    fn diagnostics(input: &str) -> Vec<(usize, usize, String)> {
+        match parse_query(input, &ResolutionModel::None) {
+            Err(SearchError::InvalidQuery(diagnostics)) => diagnostics
+                .into_iter()
+                .map(|d| (d.line, d.column, d.message))
+                .collect(),
+            _ => panic!("{input:?} should not parse"),
+        }
+    }
+
+    #[test]
+    fn tokenize_splits_a_line() {
+        let tokens = tokenize("maybe 661.7(3) keV ± 0.3 keV 2σ I>=10%");
+        let kinds: Vec<(TokenKind, &str)> = tokens.iter().map(|t| (t.kind, t.text)).collect();
+        assert_eq!(
+            kinds,
+            [
+                (TokenKind::Word, "maybe"),
+                (TokenKind::Number, "661.7(3)"),
+                (TokenKind::Word, "keV"),
+                (TokenKind::PlusMinus, "±"),
+                (TokenKind::Number, "0.3"),
+                (TokenKind::Word, "keV"),
+                (TokenKind::Number, "2"),
+                (TokenKind::Word, "σ"),
+                (TokenKind::Word, "I"),
+                (TokenKind::Comparison, ">="),
+                (TokenKind::Number, "10"),
+                (TokenKind::Percent, "%"),
+            ]
+        );
+        // Spans are byte ranges, "±" taking two bytes
+        assert_eq!(tokens[3].span, 19..21);
+        assert_eq!(tokens[4].span, 22..25);
+    }
+
+    #[test]
+    fn parse_windows() {
+        let e =
+            energies("661.7 keV 1%\n661.7 keV ± 0.3 keV 2σ\n0.2389(12) MeV\nmaybe 50 eV +/- 1 eV");
+        assert_close(e[0].lteb, 655.083);
+        assert_close(e[0].uteb, 668.317);
+        assert_close(e[1].lteb, 661.1);
+        assert_close(e[1].uteb, 662.3);
+        assert_close(e[2].lteb, 237.7);
+        assert_close(e[2].uteb, 240.1);
+        assert_close(e[3].lteb, 0.049);
+        assert_eq!(e[3].modifier, Modifier::Maybe);
+        assert_eq!(e[0].modifier, Modifier::Definitely);
+        assert_close(energies("0.2389 MEV 1%")[0].lteb, 0.2389e3 * 0.99);
+        assert_eq!(
+            diagnostics("238.9 meV 1%"),
+            [(
+                1,
+                7,
+                "unknown unit 'meV', expected eV, keV or MeV".to_string()
+            )]
+        );
+    }
+
+    #[test]
+    fn strict_bounds_exclude_the_value() {
+        let bound = |input: &str| energies(input)[0].intensity[0].clone();
+        let above = bound("661.7 keV 1% I>10%");
+        assert_eq!(above.comparison, Comparison::Above);
+        assert!(!above.accepts(10.0));
+        assert!(above.accepts(10.1));
+        assert!(bound("661.7 keV 1% I>=10%").accepts(10.0));
+        assert!(!bound("661.7 keV 1% I<10%").accepts(10.0));
+        assert!(bound("661.7 keV 1% I<=10%").accepts(10.0));
+    }
+
+    #[test]
+    fn lines_are_numbered_as_in_the_editor() {
+        let input = "# comment\n661.657 keV 1%\nT1/2 > 1 h\n\n32.19 keV 1% I/L2<1";
+        let e = energies(input);
+        assert_eq!(e[0].line, Some(2));
+        assert_eq!(e[1].line, Some(5));
+        assert_eq!(
+            e[1].intensity,
+            [IntensityConstraint {
+                reference: Some(0),
+                comparison: Comparison::Below,
+                value: 1.0,
+            }]
+        );
+        assert_eq!(
+            diagnostics(&input.replace("L2", "L3")),
+            [(5, 17, "there is no energy on query line 3".to_string())]
+        );
+        assert_eq!(
+            diagnostics(&input.replace("L2", "L5")),
+            [(5, 17, "a line cannot be compared with itself".to_string())]
+        );
+    }
+
+    #[test]
+    fn every_malformed_line_is_reported() {
+        let input = "661.7 keV 1%\n661.7 MeVV 1%\n\n661.7 keV 1\nT1/2 > 1 parsec # comment";
+        assert_eq!(
+            diagnostics(input),
+            [
+                (
+                    2,
+                    7,
+                    "unknown unit 'MeVV', expected eV, keV or MeV".to_string()
+                ),
+                (4, 12, "missing % or σ after '1'".to_string()),
+                (
+                    5,
+                    10,
+                    "unknown time unit 'parsec', expected s, min, h, d or y".to_string()
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn parse_half_life_bounds() {
+        let query = parse_query(
+            "T1/2 > 1 h\nt1/2 <= 2 d\n661.7 keV 1%",
+            &ResolutionModel::None,
+        )
+        .unwrap_or_else(|e| panic!("{e}"));
+        assert_eq!(query.half_life.min, Some(3600.0));
+        assert_eq!(query.half_life.max, Some(172800.0));
+        assert_eq!(query.energies.len(), 1);
+    }
 
-    match query_database(&energies, radiation_type) {
-        Some(map) => print_results(map, print_mode),
-        None => "No results found".to_string(),
+    #[test]
+    fn parse_durations() {
+        assert_close(parse_duration("30min").unwrap(), 1800.0);
+        assert!(parse_duration("1 fortnight").is_err());
+        assert!(parse_duration("h").is_err());
     }
 }
@@ -0,0 +1,104 @@
+/// Ratio between the FWHM and the standard deviation of a Gaussian peak.
+pub const FWHM_PER_SIGMA: f64 = 2.354_820_045;
+
+/// Energy resolution of the detector, used to derive the match window of query lines
+/// written without an uncertainty.
+#[derive(Clone, PartialEq, serde::Deserialize, serde::Serialize)]
+#[serde(tag = "type")]
+pub enum ResolutionModel {
+    /// Lines without an uncertainty only match records containing that exact energy.
+    None,
+    /// FWHM = sqrt(a + b·E + c·E²), with E and the FWHM in keV, as usual for HPGe detectors.
+    Hpge { a: f64, b: f64, c: f64 },
+    /// Relative resolution (FWHM/E, in percent) at 662 keV, scaling as 1/sqrt(E),
+    /// as usual for NaI(Tl) or LaBr3 scintillators.
+    Scintillator { percent_at_662: f64 },
+}
+
+impl Default for ResolutionModel {
+    fn default() -> Self {
+        Self::None
+    }
+}
+
+impl ResolutionModel {
+    /// Typical coefficients of a coaxial HPGe detector (about 1.9 keV FWHM at 1332 keV).
+    pub fn default_hpge() -> Self {
+        Self::Hpge {
+            a: 0.8,
+            b: 1.8e-3,
+            c: 2.0e-7,
+        }
+    }
+
+    /// Typical resolution of a 3"x3" NaI(Tl) detector.
+    pub fn default_scintillator() -> Self {
+        Self::Scintillator {
+            percent_at_662: 7.0,
+        }
+    }
+
+    /// Full width at half maximum, in keV, of a peak at `energy` keV.
+    pub fn fwhm(&self, energy: f64) -> f64 {
+        match self {
+            ResolutionModel::None => 0.0,
+            ResolutionModel::Hpge { a, b, c } => {
+                (a + b * energy + c * energy * energy).max(0.0).sqrt()
+            }
+            ResolutionModel::Scintillator { percent_at_662 } => {
+                percent_at_662 / 100.0 * (662.0 * energy.max(0.0)).sqrt()
+            }
+        }
+    }
+
+    /// Half width, in keV, of the match window at `energy` keV: half the FWHM, or `sigmas`
+    /// standard deviations of the peak when a multiplier is given.
+    pub fn half_window(&self, energy: f64, sigmas: Option<f64>) -> f64 {
+        match sigmas {
+            Some(k) => k * self.fwhm(energy) / FWHM_PER_SIGMA,
+            None => self.fwhm(energy) / 2.0,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::assert_close;
+
+    #[test]
+    fn hpge_fwhm() {
+        let model = ResolutionModel::default_hpge();
+        let e: f64 = 1332.5;
+        assert_close(model.fwhm(e), (0.8 + 1.8e-3 * e + 2.0e-7 * e * e).sqrt());
+        // A negative polynomial is no resolution at all, not NaN
+        let model = ResolutionModel::Hpge {
+            a: -1.0,
+            b: 0.0,
+            c: 0.0,
+        };
+        assert_eq!(model.fwhm(100.0), 0.0);
+    }
+
+    #[test]
+    fn scintillator_fwhm_grows_as_the_square_root() {
+        let model = ResolutionModel::default_scintillator();
+        assert_close(model.fwhm(662.0), 0.07 * 662.0);
+        assert_close(model.fwhm(4.0 * 662.0), 2.0 * 0.07 * 662.0);
+        assert_eq!(model.fwhm(-1.0), 0.0);
+        assert_eq!(ResolutionModel::None.fwhm(662.0), 0.0);
+    }
+
+    #[test]
+    fn half_windows() {
+        let model = ResolutionModel::default_scintillator();
+        let fwhm = model.fwhm(1000.0);
+        assert_close(model.half_window(1000.0, None), fwhm / 2.0);
+        assert_close(
+            model.half_window(1000.0, Some(2.0)),
+            2.0 * fwhm / FWHM_PER_SIGMA,
+        );
+        // One standard deviation is a little less than half the FWHM
+        assert!(model.half_window(1000.0, Some(1.0)) < model.half_window(1000.0, None));
+    }
+}
@@ -0,0 +1,182 @@
+use crate::app::PrintMode;
+use crate::database::TransitionResult;
+use crate::nuclide::{HalfLife, Nuclide};
+use crate::search::{Candidate, SearchResult};
+use egui_extras::{Column, TableBuilder};
+use std::cmp::Ordering;
+
+#[derive(Clone, Copy, PartialEq, serde::Deserialize, serde::Serialize)]
+#[serde(tag = "type")]
+pub enum ResultColumn {
+    Decay,
+    Score,
+    Type,
+    Energy,
+    Uncertainty,
+    Intensity,
+    Parent,
+    HalfLife,
+    Daughter,
+    Match,
+}
+
+impl ResultColumn {
+    const ALL: [ResultColumn; 10] = [
+        ResultColumn::Decay,
+        ResultColumn::Score,
+        ResultColumn::Type,
+        ResultColumn::Energy,
+        ResultColumn::Uncertainty,
+        ResultColumn::Intensity,
+        ResultColumn::Parent,
+        ResultColumn::HalfLife,
+        ResultColumn::Daughter,
+        ResultColumn::Match,
+    ];
+
+    fn title(&self) -> &'static str {
+        match self {
+            ResultColumn::Decay => "Decay",
+            ResultColumn::Score => "Score",
+            ResultColumn::Type => "Type",
+            ResultColumn::Energy => "Energy (keV)",
+            ResultColumn::Uncertainty => "Uncertainty (keV)",
+            ResultColumn::Intensity => "Intensity (%)",
+            ResultColumn::Parent => "Parent",
+            ResultColumn::HalfLife => "Half-life",
+            ResultColumn::Daughter => "Daughter",
+            ResultColumn::Match => "Match",
+        }
+    }
+
+    /// Whether the first click on the header sorts from the largest value, as for scores.
+    fn descending_first(&self) -> bool {
+        matches!(
+            self,
+            ResultColumn::Score | ResultColumn::Intensity | ResultColumn::Match
+        )
+    }
+
+    fn text(&self, c: &Candidate, r: &TransitionResult) -> String {
+        match self {
+            ResultColumn::Decay => c.decay.to_string(),
+            ResultColumn::Score => format!("{:.2}", c.score),
+            ResultColumn::Type => r.t.radiation_type.to_string(),
+            ResultColumn::Energy => format!("{}{:.3}", r.t.qualifier, r.t.energy),
+            ResultColumn::Uncertainty => format!("{:.3}", r.t.uncertainty),
+            ResultColumn::Intensity => match r.t.intensity {
+                i if i == 0.0 || i >= 0.01 => format!("{i:.3}"),
+                i => format!("{i:.2e}"),
+            },
+            ResultColumn::Parent => r.t.decay.parent.to_string(),
+            ResultColumn::HalfLife => match &r.t.decay.half_life {
+                Some(t) => t.to_string(),
+                None => String::new(),
+            },
+            ResultColumn::Daughter => r.t.decay.daughter.to_string(),
+            ResultColumn::Match => match r.found {
+                true => "✔".to_string(),
+                false => String::new(),
+            },
+        }
+    }
+
+    fn compare(
+        &self,
+        a: (&Candidate, &TransitionResult),
+        b: (&Candidate, &TransitionResult),
+    ) -> Ordering {
+        match self {
+            ResultColumn::Decay => a.0.decay.dataset.cmp(&b.0.decay.dataset),
+            ResultColumn::Score => a.0.score.total_cmp(&b.0.score),
+            ResultColumn::Type => a.1.t.radiation_type.cmp(&b.1.t.radiation_type),
+            ResultColumn::Energy => a.1.t.energy.total_cmp(&b.1.t.energy),
+            ResultColumn::Uncertainty => a.1.t.uncertainty.total_cmp(&b.1.t.uncertainty),
+            ResultColumn::Intensity => a.1.t.intensity.total_cmp(&b.1.t.intensity),
+            ResultColumn::Parent => nuclide_order(&a.1.t.decay.parent, &b.1.t.decay.parent),
+            ResultColumn::HalfLife => {
+                let seconds =
+                    |r: &TransitionResult| r.t.decay.half_life.as_ref().map(HalfLife::seconds);
+                match (seconds(a.1), seconds(b.1)) {
+                    (Some(x), Some(y)) => x.total_cmp(&y),
+                    (x, y) => x.is_none().cmp(&y.is_none()),
+                }
+            }
+            ResultColumn::Daughter => nuclide_order(&a.1.t.decay.daughter, &b.1.t.decay.daughter),
+            ResultColumn::Match => a.1.found.cmp(&b.1.found),
+        }
+    }
+}
+
+/// Orders nuclides by Z, then A, then excitation energy.
+fn nuclide_order(a: &Nuclide, b: &Nuclide) -> Ordering {
+    a.atomic_number
+        .cmp(&b.atomic_number)
+        .then(a.mass_number.cmp(&b.mass_number))
+        .then(a.level_energy.total_cmp(&b.level_energy))
+}
+
+/// Table of the records of every candidate, one row per record.
+#[derive(Default, serde::Deserialize, serde::Serialize)]
+#[serde(default)]
+pub struct ResultsTable {
+    /// Column the rows are sorted by, `None` to keep the ranking of the candidates.
+    sort_by: Option<ResultColumn>,
+    descending: bool,
+}
+
+impl ResultsTable {
+    pub fn show(&mut self, ui: &mut egui::Ui, results: &SearchResult, print_mode: &PrintMode) {
+        let mut rows: Vec<(&Candidate, &TransitionResult)> = results
+            .candidates
+            .iter()
+            .flat_map(|c| c.transitions.iter().map(move |r| (c, r)))
+            .filter(|(_, r)| *print_mode == PrintMode::Everything || r.found)
+            .collect();
+        if let Some(column) = self.sort_by {
+            // The sort is stable, so ties keep the ranking of the candidates
+            rows.sort_by(|&a, &b| match self.descending {
+                false => column.compare(a, b),
+                true => column.compare(b, a),
+            });
+        }
+
+        let row_height = ui.text_style_height(&egui::TextStyle::Body);
+        TableBuilder::new(ui)
+            .striped(true)
+            .resizable(true)
+            .cell_layout(egui::Layout::left_to_right(egui::Align::Center))
+            .column(Column::initial(220.0).at_least(60.0).clip(true))
+            .columns(Column::auto().at_least(40.0), ResultColumn::ALL.len() - 1)
+            .header(row_height + 6.0, |mut header| {
+                for column in ResultColumn::ALL {
+                    header.col(|ui| {
+                        let arrow = match (self.sort_by == Some(column), self.descending) {
+                            (true, false) => " ⏶",
+                            (true, true) => " ⏷",
+                            (false, _) => "",
+                        };
+                        let title = format!("{}{arrow}", column.title());
+                        if ui.add(egui::Button::new(title).frame(false)).clicked() {
+                            if self.sort_by == Some(column) {
+                                self.descending = !self.descending;
+                            } else {
+                                self.sort_by = Some(column);
+                                self.descending = column.descending_first();
+                            }
+                        }
+                    });
+                }
+            })
+            .body(|body| {
+                body.rows(row_height, rows.len(), |mut row| {
+                    let (c, r) = rows[row.index()];
+                    for column in ResultColumn::ALL {
+                        row.col(|ui| {
+                            ui.label(column.text(c, r));
+                        });
+                    }
+                });
+            });
+    }
+}
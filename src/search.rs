@@ -0,0 +1,669 @@
+use crate::app::RadiationType;
+use crate::background::{default_background, BackgroundLine};
+use crate::chain::{chain, heads};
+use crate::cooling::cooling;
+use crate::database::{
+    decays_of, energy_in_transition_range, filter_by_decay_type, mark_found_transitions,
+    query_database, Transition, TransitionResult,
+};
+use crate::nuclide::{Decay, HalfLife, Nuclide};
+use crate::query_parser::{Diagnostic, Energy, Modifier};
+use std::cmp::Ordering;
+use std::collections::HashSet;
+
+/// Order in which the candidates are listed.
+#[derive(Debug, Clone, Copy, PartialEq, serde::Deserialize, serde::Serialize)]
+#[serde(tag = "type")]
+pub enum CandidateOrder {
+    /// Best score first.
+    Score,
+    /// Lightest parent first.
+    MassNumber,
+    /// Parent with the lowest Z first.
+    AtomicNumber,
+    /// Alphabetically by the element symbol of the parent, then by mass number.
+    Name,
+    /// Shortest-lived parent first, unknown half-lives last.
+    HalfLife,
+}
+
+impl CandidateOrder {
+    pub const ALL: [CandidateOrder; 5] = [
+        CandidateOrder::Score,
+        CandidateOrder::MassNumber,
+        CandidateOrder::AtomicNumber,
+        CandidateOrder::Name,
+        CandidateOrder::HalfLife,
+    ];
+
+    fn compare(&self, a: &Candidate, b: &Candidate) -> Ordering {
+        let (p, q) = (&a.decay.parent, &b.decay.parent);
+        match self {
+            CandidateOrder::Score => Ordering::Equal,
+            CandidateOrder::MassNumber => p.mass_number.cmp(&q.mass_number),
+            CandidateOrder::AtomicNumber => p.atomic_number.cmp(&q.atomic_number),
+            CandidateOrder::Name => p
+                .symbol()
+                .cmp(q.symbol())
+                .then(p.mass_number.cmp(&q.mass_number)),
+            CandidateOrder::HalfLife => {
+                let seconds = |c: &Candidate| c.decay.half_life.as_ref().map(HalfLife::seconds);
+                match (seconds(a), seconds(b)) {
+                    (Some(x), Some(y)) => x.total_cmp(&y),
+                    (x, y) => x.is_none().cmp(&y.is_none()),
+                }
+            }
+        }
+        // Ties are broken by score, then by dataset, so that the order never depends on
+        // the order the decays were found in
+        .then_with(|| b.score.total_cmp(&a.score))
+        .then_with(|| a.decay.dataset.cmp(&b.decay.dataset))
+    }
+}
+
+impl std::fmt::Display for CandidateOrder {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let s = match self {
+            CandidateOrder::Score => "score",
+            CandidateOrder::MassNumber => "mass number",
+            CandidateOrder::AtomicNumber => "Z",
+            CandidateOrder::Name => "name",
+            CandidateOrder::HalfLife => "half-life",
+        };
+        write!(f, "{s}")
+    }
+}
+
+impl std::str::FromStr for CandidateOrder {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "score" => Ok(CandidateOrder::Score),
+            "a" | "mass" => Ok(CandidateOrder::MassNumber),
+            "z" => Ok(CandidateOrder::AtomicNumber),
+            "name" => Ok(CandidateOrder::Name),
+            "half-life" | "t" => Ok(CandidateOrder::HalfLife),
+            _ => Err(format!("unknown sort order: {s}")),
+        }
+    }
+}
+
+/// Bounds, in seconds, on the half-life of the parents of the candidates.
+#[derive(Debug, Clone, Default, PartialEq, serde::Deserialize, serde::Serialize)]
+#[serde(default)]
+pub struct HalfLifeFilter {
+    pub min: Option<f64>,
+    pub max: Option<f64>,
+}
+
+impl HalfLifeFilter {
+    /// Decays of unknown half-life are always accepted, for lack of evidence against them.
+    pub fn accepts(&self, half_life: Option<&HalfLife>) -> bool {
+        let Some(t) = half_life.map(HalfLife::seconds) else {
+            return true;
+        };
+        self.min.map_or(true, |min| t >= min) && self.max.map_or(true, |max| t <= max)
+    }
+
+    /// Filter accepting only what both `self` and `other` accept.
+    pub fn and(&self, other: &HalfLifeFilter) -> HalfLifeFilter {
+        let pick = |a: Option<f64>, b: Option<f64>, f: fn(f64, f64) -> f64| match (a, b) {
+            (Some(a), Some(b)) => Some(f(a, b)),
+            (a, b) => a.or(b),
+        };
+        HalfLifeFilter {
+            min: pick(self.min, other.min, f64::max),
+            max: pick(self.max, other.max, f64::min),
+        }
+    }
+}
+
+#[derive(Clone, PartialEq, serde::Deserialize, serde::Serialize)]
+#[serde(default)]
+pub struct SearchOptions {
+    /// Only keep the decays explaining every query line not marked as optional ("maybe"),
+    /// instead of ranking partial matches.
+    pub require_all_lines: bool,
+    /// Number of best ranked candidates returned.
+    pub max_candidates: usize,
+    /// Order of the candidates returned; the best ranked ones are kept whatever the order.
+    pub order: CandidateOrder,
+    pub half_life: HalfLifeFilter,
+    /// Time in seconds since the end of the irradiation, `None` to ignore decay and ingrowth.
+    pub cooling_time: Option<f64>,
+    /// Group each parent with its descendants in secular equilibrium (e.g. 226Ra with 214Pb
+    /// and 214Bi) into one candidate, instead of ranking every decay on its own.
+    pub group_chains: bool,
+    /// Flag the query lines explained by the background library, which then need not be
+    /// explained by the candidates.
+    pub flag_background: bool,
+    pub background: Vec<BackgroundLine>,
+}
+
+impl Default for SearchOptions {
+    fn default() -> Self {
+        Self {
+            require_all_lines: false,
+            max_candidates: 20,
+            order: CandidateOrder::Score,
+            half_life: HalfLifeFilter::default(),
+            cooling_time: None,
+            group_chains: false,
+            flag_background: false,
+            background: default_background(),
+        }
+    }
+}
+
+/// A decay whose radiation records are consistent with the query.
+pub struct Candidate {
+    pub decay: Decay,
+    /// Every record of the decay, flagged when it matches one of the query energies.
+    pub transitions: Vec<TransitionResult>,
+    /// Indexes (into the query energies) of the lines explained by this decay.
+    pub matched_lines: Vec<usize>,
+    /// The subset of `matched_lines` that are optional ("maybe") lines.
+    pub optional_lines: Vec<usize>,
+    /// The subset of `matched_lines` whose intensity relative to another matched line is
+    /// outside the bounds given in the query.
+    pub intensity_mismatches: Vec<usize>,
+    /// Summed intensity (per 100 decays) of the records matching the query.
+    pub matched_intensity: f64,
+    /// Summed intensity of the records that are within the energy range of the query and
+    /// at least as intense as the weakest matched record, yet were not observed.
+    pub missing_intensity: f64,
+    /// Ranking score between 0 and 1, see [`search`].
+    pub score: f64,
+    /// With a cooling time, the precursor the parent grows in from, if that keeps it active.
+    pub grown_in_from: Option<Nuclide>,
+    /// When grouping decay chains, the descendants whose records are included with the ones of
+    /// the parent, scaled by their activity relative to it.
+    pub chain: Vec<Nuclide>,
+}
+
+impl Candidate {
+    fn new(energies: &[Energy], transitions: Vec<TransitionResult>) -> Self {
+        let matched_lines: Vec<usize> = (0..energies.len())
+            .filter(|&i| {
+                transitions
+                    .iter()
+                    .any(|r| energy_in_transition_range(&energies[i], &r.t))
+            })
+            .collect();
+
+        // Intensity of the strongest record explaining each query line
+        let line_intensity = |i: usize| {
+            transitions
+                .iter()
+                .filter(|r| energy_in_transition_range(&energies[i], &r.t))
+                .map(|r| r.t.intensity)
+                .fold(0.0, f64::max)
+        };
+        let intensity_mismatches: Vec<usize> = matched_lines
+            .iter()
+            .copied()
+            .filter(|&i| {
+                energies[i].intensity.iter().any(|c| match c.reference {
+                    Some(j) if matched_lines.contains(&j) && line_intensity(j) > 0.0 => {
+                        !c.accepts(line_intensity(i) / line_intensity(j))
+                    }
+                    _ => false,
+                })
+            })
+            .collect();
+
+        let matched_intensity: f64 = transitions
+            .iter()
+            .filter(|r| r.found)
+            .map(|r| r.t.intensity)
+            .sum();
+        let weakest_match = transitions
+            .iter()
+            .filter(|r| r.found)
+            .map(|r| r.t.intensity)
+            .fold(f64::INFINITY, f64::min);
+        // Energy range covered by the query lines of a radiation type
+        let query_range = |t: RadiationType| {
+            let lines = energies.iter().filter(|e| e.radiation_type == Some(t));
+            lines.fold((f64::INFINITY, 0.0), |(lo, hi), e| {
+                (f64::min(lo, e.lteb), f64::max(hi, e.uteb))
+            })
+        };
+        let missing_intensity: f64 = transitions
+            .iter()
+            .filter(|r| !r.found && r.t.qualifier.is_matchable())
+            .filter(|r| {
+                let (lowest, highest) = query_range(r.t.radiation_type);
+                lowest <= r.t.energy && r.t.energy <= highest
+            })
+            .filter(|r| r.t.intensity >= weakest_match)
+            .map(|r| r.t.intensity)
+            .sum();
+
+        let is_optional = |i: &usize| energies[*i].modifier == Modifier::Maybe;
+        let optional_lines: Vec<usize> =
+            matched_lines.iter().copied().filter(is_optional).collect();
+        let n_optional = energies
+            .iter()
+            .filter(|e| e.modifier == Modifier::Maybe)
+            .count();
+        let n_required = energies.len() - n_optional;
+
+        let coverage = match n_required {
+            0 => 1.0,
+            n => (matched_lines.len() - optional_lines.len()) as f64 / n as f64,
+        };
+        let optional_bonus = match n_optional {
+            0 => 1.0,
+            n => (1.0 + optional_lines.len() as f64 / n as f64) / 2.0,
+        };
+        let consistency = (matched_intensity + 1.0) / (matched_intensity + missing_intensity + 1.0);
+        let strength = 0.5 + 0.25 * (1.0 + matched_intensity).log10().min(2.0);
+
+        Candidate {
+            // There is at least the record that matched the query
+            decay: transitions[0].t.decay.clone(),
+            transitions,
+            matched_lines,
+            optional_lines,
+            matched_intensity,
+            missing_intensity,
+            score: coverage
+                * consistency
+                * strength
+                * optional_bonus
+                * 0.5f64.powi(intensity_mismatches.len() as i32),
+            intensity_mismatches,
+            grown_in_from: None,
+            chain: vec![],
+        }
+    }
+}
+
+/// Candidates for the chains containing the decays that explain the query, see
+/// [`SearchOptions::group_chains`].
+fn chain_candidates(energies: &[Energy], require_all: bool) -> Vec<Candidate> {
+    let radiation_types: HashSet<RadiationType> =
+        energies.iter().filter_map(|e| e.radiation_type).collect();
+    let mut chain_heads: Vec<Nuclide> = vec![];
+    for transitions in query_database(energies, false).into_values() {
+        for head in heads(&transitions[0].t.decay) {
+            if !chain_heads.contains(&head) {
+                chain_heads.push(head);
+            }
+        }
+    }
+    let required: Vec<usize> = (0..energies.len())
+        .filter(|&i| energies[i].modifier == Modifier::Definitely)
+        .collect();
+    // One chain per decay of each head, so that every candidate is labelled with its own
+    // decay (e.g. 40K has β- and EC ones)
+    chain_heads
+        .iter()
+        .flat_map(decays_of)
+        .filter_map(|head| {
+            let members = chain(&head);
+            let mut transitions: Vec<Transition> = vec![];
+            for m in &members {
+                for mut t in filter_by_decay_type(&m.decay.dataset, &radiation_types) {
+                    t.intensity *= m.fraction;
+                    transitions.push(t);
+                }
+            }
+            if transitions.is_empty() {
+                return None;
+            }
+            transitions.sort_by(|a, b| a.energy.total_cmp(&b.energy));
+            let mut c = Candidate::new(energies, mark_found_transitions(energies, transitions));
+            c.decay = head.clone();
+            for m in &members {
+                if m.decay.parent != head.parent && !c.chain.contains(&m.decay.parent) {
+                    c.chain.push(m.decay.parent.clone());
+                }
+            }
+            Some(c)
+        })
+        .filter(|c| !c.matched_lines.is_empty())
+        .filter(|c| !require_all || required.iter().all(|i| c.matched_lines.contains(i)))
+        .collect()
+}
+
+pub struct SearchResult {
+    /// The best ranked candidates, in the order given in the search options.
+    pub candidates: Vec<Candidate>,
+    /// Indexes (into the query energies) of the lines no candidate explains, besides the
+    /// background ones; candidates ranked beyond `max_candidates` count as explaining too.
+    pub unmatched_lines: Vec<usize>,
+    /// Indexes of the lines explained by the background library, with the name of the peak.
+    pub background_lines: Vec<(usize, String)>,
+    /// Line of the query each energy was written on, the number the lines above are shown
+    /// with (as in the editor and the diagnostics).
+    pub query_lines: Vec<usize>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum SearchError {
+    /// The query has no energies in it.
+    EmptyQuery,
+    /// Some lines of the query could not be parsed.
+    InvalidQuery(Vec<Diagnostic>),
+}
+
+impl std::fmt::Display for SearchError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SearchError::EmptyQuery => write!(f, "the search query is empty"),
+            SearchError::InvalidQuery(diagnostics) => {
+                write!(f, "the search query has errors:")?;
+                for d in diagnostics {
+                    write!(f, "\n  {d}")?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+impl std::error::Error for SearchError {}
+
+/// Looks for the decays that explain the energies in `energies` and ranks them.
+/// Energies without a radiation type of their own are taken as `radiation_type`.
+///
+/// The score of a candidate is the product of
+/// - the fraction of required query lines it explains,
+/// - when there are optional ("maybe") lines, a factor from 0.5 to 1 growing with the
+///   fraction of them it explains,
+/// - `(matched + 1) / (matched + missing + 1)`, with the matched and missing intensities of
+///   the candidate, so that strong lines expected but not observed count against it,
+/// - a factor from 0.5 to 1 growing with the matched intensity (logarithmically, up to 100
+///   per 100 decays), so that lines from weak branches are less convincing,
+/// - one half for each line whose intensity relative to another one is outside the bounds
+///   written in the query (e.g. `I/L1>0.5`).
+///
+/// With a cooling time, the score is also multiplied by the activity of the parent at that time
+/// relative to the activity produced: what is left of the parent, or what grew in from one of
+/// its precursors in the database when that is more (e.g. 137mBa fed by 137Cs).
+///
+/// Bounds on intensities per 100 decays (e.g. `I>10%`) are not part of the score: records
+/// outside them do not explain the line at all.
+///
+/// Lines flagged as background are handled as optional lines: candidates do not need to
+/// explain them, yet get the bonus of optional lines when they do (e.g. 511 keV for β+
+/// emitters). Decays explaining optional and background lines only are not candidates,
+/// unless every line of the query is optional.
+///
+/// When grouping decay chains, the candidates are the chains headed by each decay of the
+/// parents of the decays explaining the query and of their longer-lived ancestors, each
+/// scored as one decay with the records of all its members.
+pub fn search(
+    energies: &[Energy],
+    radiation_type: &RadiationType,
+    options: &SearchOptions,
+) -> Result<SearchResult, SearchError> {
+    if energies.is_empty() {
+        return Err(SearchError::EmptyQuery);
+    }
+
+    let mut energies: Vec<Energy> = energies
+        .iter()
+        .map(|e| Energy {
+            radiation_type: e.radiation_type.or(Some(*radiation_type)),
+            ..e.clone()
+        })
+        .collect();
+    let background_lines = match options.flag_background {
+        true => flag_background(&mut energies, &options.background),
+        false => vec![],
+    };
+    let candidates: Vec<Candidate> = match options.group_chains {
+        false => query_database(&energies, options.require_all_lines)
+            .into_values()
+            .map(|transitions| Candidate::new(&energies, transitions))
+            .collect(),
+        true => chain_candidates(&energies, options.require_all_lines),
+    };
+    Ok(rank(&energies, candidates, background_lines, options))
+}
+
+/// Marks the lines of `energies` explained by the `background` library as optional and
+/// returns their indexes with the name of the peak.
+fn flag_background(energies: &mut [Energy], background: &[BackgroundLine]) -> Vec<(usize, String)> {
+    let mut lines = vec![];
+    for (i, e) in energies.iter_mut().enumerate() {
+        if let Some(b) = background.iter().find(|b| b.explains(e)) {
+            lines.push((i, b.name.clone()));
+            e.modifier = Modifier::Maybe;
+        }
+    }
+    lines
+}
+
+/// Filters the candidates for `energies` with the search options and keeps the best ranked
+/// ones, see [`search`].
+fn rank(
+    energies: &[Energy],
+    mut candidates: Vec<Candidate>,
+    background_lines: Vec<(usize, String)>,
+    options: &SearchOptions,
+) -> SearchResult {
+    if energies.iter().any(|e| e.modifier == Modifier::Definitely) {
+        candidates.retain(|c| c.matched_lines.len() > c.optional_lines.len());
+    }
+    candidates.retain(|c| options.half_life.accepts(c.decay.half_life.as_ref()));
+    if let Some(t) = options.cooling_time {
+        for c in &mut candidates {
+            let cooled = cooling(&c.decay, t);
+            c.score *= cooled.factor;
+            c.grown_in_from = cooled.grown_in_from;
+        }
+    }
+    // Before keeping the best candidates only, so that a line explained by a candidate left
+    // out is not reported as unexplained
+    let unmatched_lines = (0..energies.len())
+        .filter(|i| !candidates.iter().any(|c| c.matched_lines.contains(i)))
+        .filter(|i| !background_lines.iter().any(|(j, _)| j == i))
+        .collect();
+    let query_lines = energies
+        .iter()
+        .enumerate()
+        .map(|(i, e)| e.line.unwrap_or(i + 1))
+        .collect();
+
+    candidates.sort_by(|a, b| CandidateOrder::Score.compare(a, b));
+    candidates.truncate(options.max_candidates);
+    candidates.sort_by(|a, b| options.order.compare(a, b));
+
+    SearchResult {
+        candidates,
+        unmatched_lines,
+        background_lines,
+        query_lines,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::query_parser::{Comparison, IntensityConstraint};
+    use crate::testing::{assert_close, decay, gamma, line};
+
+    fn candidate(energies: &[Energy], transitions: Vec<Transition>) -> Candidate {
+        Candidate::new(energies, mark_found_transitions(energies, transitions))
+    }
+
+    #[test]
+    fn score_is_coverage_consistency_and_strength() {
+        let energies = [
+            line(100.0, Modifier::Definitely),
+            line(200.0, Modifier::Definitely),
+        ];
+        let a = decay("A", 50, 120);
+        // 150 keV is within the query range and stronger than the match, yet not observed
+        let c = candidate(
+            &energies,
+            vec![gamma(&a, 100.0, 9.0), gamma(&a, 150.0, 30.0)],
+        );
+        assert_eq!(c.matched_lines, [0]);
+        assert_close(c.matched_intensity, 9.0);
+        assert_close(c.missing_intensity, 30.0);
+        // Coverage 1/2, consistency 10/40, strength 0.5 + 0.25 log10(10)
+        assert_close(c.score, 0.5 * 0.25 * 0.75);
+    }
+
+    #[test]
+    fn intensity_mismatches_halve_the_score() {
+        let mut energies = [
+            line(100.0, Modifier::Definitely),
+            line(200.0, Modifier::Definitely),
+        ];
+        let a = decay("A", 50, 120);
+        let transitions = || vec![gamma(&a, 100.0, 10.0), gamma(&a, 200.0, 2.0)];
+        let consistent = candidate(&energies, transitions());
+        assert!(consistent.intensity_mismatches.is_empty());
+
+        energies[1].intensity.push(IntensityConstraint {
+            reference: Some(0),
+            comparison: Comparison::AtLeast,
+            value: 0.5,
+        });
+        let c = candidate(&energies, transitions());
+        assert_eq!(c.matched_lines, [0, 1]);
+        assert_eq!(c.intensity_mismatches, [1]);
+        assert_close(c.score, consistent.score / 2.0);
+    }
+
+    #[test]
+    fn optional_lines_give_a_bonus() {
+        let energies = [
+            line(100.0, Modifier::Definitely),
+            line(200.0, Modifier::Maybe),
+            line(300.0, Modifier::Maybe),
+        ];
+        let a = decay("A", 50, 120);
+        let c = candidate(
+            &energies,
+            vec![gamma(&a, 100.0, 90.0), gamma(&a, 200.0, 9.0)],
+        );
+        assert_eq!(c.optional_lines, [1]);
+        // Full coverage and consistency, strength 1, half of the optional lines explained
+        assert_close(c.score, 0.75);
+    }
+
+    #[test]
+    fn unmatched_lines_include_candidates_left_out() {
+        let energies = [
+            line(100.0, Modifier::Definitely),
+            line(200.0, Modifier::Definitely),
+            line(300.0, Modifier::Definitely),
+        ];
+        let (a, b) = (decay("A", 50, 120), decay("B", 60, 140));
+        let candidates = vec![
+            candidate(&energies, vec![gamma(&a, 100.0, 50.0)]),
+            candidate(&energies, vec![gamma(&b, 200.0, 1.0)]),
+        ];
+        let options = SearchOptions {
+            max_candidates: 1,
+            ..SearchOptions::default()
+        };
+        let result = rank(&energies, candidates, vec![], &options);
+        assert_eq!(result.candidates.len(), 1);
+        assert_eq!(result.candidates[0].decay.dataset, "A");
+        assert_eq!(result.unmatched_lines, [2]);
+        assert_eq!(result.query_lines, [1, 2, 3]);
+    }
+
+    #[test]
+    fn candidates_must_explain_a_required_line() {
+        let mut energies = [
+            line(100.0, Modifier::Definitely),
+            line(511.0, Modifier::Maybe),
+        ];
+        let (a, b) = (decay("A", 50, 120), decay("B", 60, 140));
+        let candidates = |energies: &[Energy]| {
+            vec![
+                candidate(energies, vec![gamma(&a, 100.0, 50.0)]),
+                candidate(energies, vec![gamma(&b, 511.0, 100.0)]),
+            ]
+        };
+        let result = rank(
+            &energies,
+            candidates(&energies),
+            vec![],
+            &SearchOptions::default(),
+        );
+        let datasets: Vec<&str> = result
+            .candidates
+            .iter()
+            .map(|c| c.decay.dataset.as_str())
+            .collect();
+        assert_eq!(datasets, ["A"]);
+        assert_eq!(result.unmatched_lines, [1]);
+
+        // Unless there are no required lines at all
+        energies[0].modifier = Modifier::Maybe;
+        let result = rank(
+            &energies,
+            candidates(&energies),
+            vec![],
+            &SearchOptions::default(),
+        );
+        assert_eq!(result.candidates.len(), 2);
+        assert!(result.unmatched_lines.is_empty());
+    }
+
+    #[test]
+    fn ties_are_broken_by_score_then_dataset() {
+        let energies = [line(100.0, Modifier::Definitely)];
+        let candidates = || {
+            [
+                ("B", 120, 50.0),
+                ("C", 120, 1.0),
+                ("D", 100, 50.0),
+                ("A", 120, 50.0),
+            ]
+            .iter()
+            .map(|&(dataset, a, intensity)| {
+                let d = decay(dataset, 50, a);
+                candidate(&energies, vec![gamma(&d, 100.0, intensity)])
+            })
+            .collect()
+        };
+        let datasets = |order| {
+            let options = SearchOptions {
+                order,
+                ..SearchOptions::default()
+            };
+            let result = rank(&energies, candidates(), vec![], &options);
+            let datasets: Vec<String> = result
+                .candidates
+                .into_iter()
+                .map(|c| c.decay.dataset)
+                .collect();
+            datasets
+        };
+        assert_eq!(datasets(CandidateOrder::Score), ["A", "B", "D", "C"]);
+        assert_eq!(datasets(CandidateOrder::MassNumber), ["D", "A", "B", "C"]);
+    }
+
+    #[test]
+    fn background_lines_are_optional() {
+        let mut energies = [
+            line(100.0, Modifier::Definitely),
+            line(511.5, Modifier::Definitely),
+        ];
+        let background_lines = flag_background(&mut energies, &default_background());
+        assert_eq!(background_lines, [(1, "annihilation".to_string())]);
+        assert_eq!(energies[0].modifier, Modifier::Definitely);
+        assert_eq!(energies[1].modifier, Modifier::Maybe);
+
+        // A candidate need not explain them, and they are not reported as unexplained
+        let a = decay("A", 50, 120);
+        let candidates = vec![candidate(&energies, vec![gamma(&a, 100.0, 99.0)])];
+        let options = SearchOptions::default();
+        let result = rank(&energies, candidates, background_lines, &options);
+        assert_close(result.candidates[0].score, 0.5);
+        assert!(result.unmatched_lines.is_empty());
+        assert_eq!(result.background_lines.len(), 1);
+    }
+}
@@ -0,0 +1,438 @@
+//! Readers for the spectrum files written by common MCA software: ORTEC `.Spe` (ASCII) and
+//! `.Chn` (binary), ANSI N42.42 XML, and plain CSV with two columns (channel or energy, counts).
+
+use crate::fit::{fit_polynomial, polynomial};
+use std::path::Path;
+
+/// A measured spectrum, one number of counts per channel.
+#[derive(Clone, Default)]
+pub struct Spectrum {
+    pub counts: Vec<f64>,
+    /// Energy calibration in keV, E = c[0] + c[1]·ch + c[2]·ch², empty when unknown.
+    pub calibration: Vec<f64>,
+    /// Standard uncertainty in keV of the energies given by `calibration`, 0 when unknown.
+    pub calibration_uncertainty: f64,
+    /// Live and real times of the measurement in seconds, when given.
+    pub live_time: Option<f64>,
+    pub real_time: Option<f64>,
+}
+
+impl Spectrum {
+    /// Energy in keV at `channel`, `None` for uncalibrated spectra.
+    pub fn energy(&self, channel: f64) -> Option<f64> {
+        (!self.calibration.is_empty()).then(|| polynomial(&self.calibration, channel))
+    }
+
+    /// Width in keV of a channel at `channel`, `None` for uncalibrated spectra.
+    pub fn kev_per_channel(&self, channel: f64) -> Option<f64> {
+        let derivative: Vec<f64> = (1..self.calibration.len())
+            .map(|k| k as f64 * self.calibration[k])
+            .collect();
+        (!derivative.is_empty()).then(|| polynomial(&derivative, channel))
+    }
+}
+
+/// Reads a spectrum file, choosing the format from the extension of `name`.
+pub fn read_spectrum(name: &str, bytes: &[u8]) -> Result<Spectrum, String> {
+    let extension = Path::new(name)
+        .extension()
+        .and_then(|e| e.to_str())
+        .unwrap_or("")
+        .to_lowercase();
+    let text = || std::str::from_utf8(bytes).map_err(|e| format!("not a text file: {e}"));
+    match extension.as_str() {
+        "spe" => read_spe(text()?),
+        "chn" => read_chn(bytes),
+        "n42" | "xml" => read_n42(text()?),
+        "csv" | "txt" => read_csv(text()?),
+        _ => Err(format!(
+            "unknown spectrum format {extension:?}, expected .Spe, .Chn, .n42 or .csv"
+        )),
+    }
+}
+
+/// Most channels a spectrum is read with, well above those of any MCA (16384 or 65536), so that
+/// a corrupt file cannot have us allocate gigabytes.
+const MAX_CHANNELS: usize = 1 << 20;
+
+fn numbers(line: &str) -> Vec<f64> {
+    line.split_whitespace()
+        .map_while(|w| w.parse().ok())
+        .collect()
+}
+
+/// Reads an ORTEC ASCII spectrum: `$SECTION:` headers, each followed by its values.
+pub fn read_spe(text: &str) -> Result<Spectrum, String> {
+    let mut spectrum = Spectrum::default();
+    let mut lines = text.lines().map(str::trim);
+    let mut fit = vec![];
+    while let Some(line) = lines.next() {
+        match line {
+            "$DATA:" => {
+                let range = numbers(lines.next().unwrap_or(""));
+                let [first, last] = range[..] else {
+                    return Err("$DATA: expected the first and last channels".to_string());
+                };
+                if last < first {
+                    return Err(format!(
+                        "$DATA: last channel {last} before the first {first}"
+                    ));
+                }
+                let n = (last - first) as usize + 1;
+                if n > MAX_CHANNELS {
+                    return Err(format!("$DATA: more than {MAX_CHANNELS} channels"));
+                }
+                spectrum.counts = lines
+                    .by_ref()
+                    .take(n)
+                    .map(|l| {
+                        l.parse()
+                            .map_err(|_| format!("$DATA: invalid counts {l:?}"))
+                    })
+                    .collect::<Result<_, _>>()?;
+                if spectrum.counts.len() != n {
+                    return Err(format!("$DATA: expected {n} channels"));
+                }
+            }
+            "$MEAS_TIM:" => {
+                if let [live, real] = numbers(lines.next().unwrap_or(""))[..] {
+                    spectrum.live_time = Some(live);
+                    spectrum.real_time = Some(real);
+                }
+            }
+            // Number of coefficients, then the coefficients (and the unit)
+            "$MCA_CAL:" => {
+                let n = numbers(lines.next().unwrap_or("")).first().copied();
+                let c = numbers(lines.next().unwrap_or(""));
+                spectrum.calibration = c.into_iter().take(n.unwrap_or(0.0) as usize).collect();
+            }
+            "$ENER_FIT:" => fit = numbers(lines.next().unwrap_or("")),
+            _ => {}
+        }
+    }
+    if spectrum.calibration.is_empty() {
+        spectrum.calibration = fit;
+    }
+    if spectrum.calibration.iter().all(|c| *c == 0.0) {
+        spectrum.calibration.clear();
+    }
+    match spectrum.counts.is_empty() {
+        true => Err("no $DATA section".to_string()),
+        false => Ok(spectrum),
+    }
+}
+
+/// Reads an ORTEC binary spectrum: a 32-byte header, the counts as 32-bit integers, then a
+/// trailer with the energy calibration.
+pub fn read_chn(bytes: &[u8]) -> Result<Spectrum, String> {
+    let u16_at = |i: usize| {
+        bytes
+            .get(i..i + 2)
+            .map(|b| u16::from_le_bytes([b[0], b[1]]))
+    };
+    let u32_at = |i: usize| {
+        bytes
+            .get(i..i + 4)
+            .map(|b| u32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+    };
+    let f32_at = |i: usize| u32_at(i).map(f32::from_bits);
+    if u16_at(0) != Some(0xffff) {
+        return Err("not a .Chn file".to_string());
+    }
+    let n = u16_at(30).ok_or("truncated header")? as usize;
+    if n == 0 {
+        return Err("no channels".to_string());
+    }
+    let counts = (0..n)
+        .map(|i| u32_at(32 + 4 * i).map(f64::from))
+        .collect::<Option<Vec<f64>>>()
+        .ok_or("truncated channel data")?;
+    // Times are given in ticks of 20 ms
+    let ticks = |i: usize| u32_at(i).map(|t| t as f64 * 0.02);
+    let trailer = 32 + 4 * n;
+    let calibration = match u16_at(trailer).map(|t| t as i16) {
+        Some(-101) => vec![f32_at(trailer + 4), f32_at(trailer + 8)],
+        Some(-102) => vec![
+            f32_at(trailer + 4),
+            f32_at(trailer + 8),
+            f32_at(trailer + 12),
+        ],
+        _ => vec![],
+    };
+    let calibration: Vec<f64> = calibration.into_iter().flatten().map(f64::from).collect();
+    Ok(Spectrum {
+        counts,
+        calibration: match calibration.iter().all(|c| *c == 0.0) {
+            true => vec![],
+            false => calibration,
+        },
+        calibration_uncertainty: 0.0,
+        live_time: ticks(12),
+        real_time: ticks(8),
+    })
+}
+
+/// Duration in seconds of an ISO 8601 duration as used by N42, e.g. "PT1H2M3.5S".
+fn iso_duration(text: &str) -> Option<f64> {
+    let mut rest = text.trim().strip_prefix("PT")?;
+    let mut seconds = 0.0;
+    for (unit, factor) in [('H', 3600.0), ('M', 60.0), ('S', 1.0)] {
+        if let Some((value, tail)) = rest.split_once(unit) {
+            seconds += value.parse::<f64>().ok()? * factor;
+            rest = tail;
+        }
+    }
+    rest.is_empty().then_some(seconds)
+}
+
+/// Reads the first spectrum of an ANSI N42.42 XML document (2006 or 2011 schema).
+pub fn read_n42(text: &str) -> Result<Spectrum, String> {
+    let document = roxmltree::Document::parse(text).map_err(|e| e.to_string())?;
+    let element = |name: &str| {
+        document
+            .descendants()
+            .find(|n| n.is_element() && n.tag_name().name() == name)
+    };
+    let data = element("ChannelData").ok_or("no ChannelData element")?;
+    let values = numbers(data.text().unwrap_or(""));
+    let counts = match data.attribute("compressionCode") {
+        // Runs of empty channels are written as a zero followed by their number
+        Some("CountedZeroes") => {
+            let mut counts = vec![];
+            let mut values = values.into_iter();
+            while let Some(v) = values.next() {
+                match v == 0.0 {
+                    true => {
+                        let run = values.next().ok_or("unterminated run of zeroes")?;
+                        if counts.len() as f64 + run > MAX_CHANNELS as f64 {
+                            return Err(format!("more than {MAX_CHANNELS} channels"));
+                        }
+                        counts.extend(std::iter::repeat(0.0).take(run as usize));
+                    }
+                    false => counts.push(v),
+                }
+            }
+            counts
+        }
+        _ => values,
+    };
+    if counts.is_empty() {
+        return Err("empty ChannelData".to_string());
+    }
+    let calibration = element("CoefficientValues")
+        .or_else(|| element("Coefficients"))
+        .map(|c| numbers(c.text().unwrap_or("")))
+        .unwrap_or_default();
+    let duration = |name: &str| element(name).and_then(|e| iso_duration(e.text()?));
+    Ok(Spectrum {
+        counts,
+        calibration,
+        calibration_uncertainty: 0.0,
+        live_time: duration("LiveTimeDuration").or_else(|| duration("LiveTime")),
+        real_time: duration("RealTimeDuration").or_else(|| duration("RealTime")),
+    })
+}
+
+/// Reads a spectrum with one channel per row: channel number or energy in keV, then counts.
+/// Rows before the first one starting with two numbers (headers) are skipped, as are blank
+/// lines and lines starting with #; any other row is an error. A first column of consecutive
+/// integers holds channel numbers (from 0, 1 or any other), and the counts keep them; one of
+/// increasing values holds energies, to which the calibration is fitted.
+pub fn read_csv(text: &str) -> Result<Spectrum, String> {
+    let mut rows: Vec<(f64, f64)> = vec![];
+    // Line of the file of each row, for the errors
+    let mut lines = vec![];
+    for (i, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let mut fields = line.split([',', ';', '\t', ' ']).filter(|f| !f.is_empty());
+        let mut number = || fields.next()?.trim().parse::<f64>().ok();
+        match (number(), number()) {
+            (Some(x), Some(y)) => {
+                rows.push((x, y));
+                lines.push(i + 1);
+            }
+            _ if rows.is_empty() => {}
+            _ => return Err(format!("line {}: expected two numbers", i + 1)),
+        }
+    }
+    if rows.is_empty() {
+        return Err("no rows of two numbers".to_string());
+    }
+    let first = rows[0].0;
+    let channels = first >= 0.0
+        && first.fract() == 0.0
+        && rows
+            .iter()
+            .enumerate()
+            .all(|(i, r)| r.0 == first + i as f64);
+    let counts = rows.iter().map(|r| r.1);
+    if channels {
+        if first + rows.len() as f64 > MAX_CHANNELS as f64 {
+            return Err(format!("more than {MAX_CHANNELS} channels"));
+        }
+        return Ok(Spectrum {
+            counts: std::iter::repeat(0.0)
+                .take(first as usize)
+                .chain(counts)
+                .collect(),
+            ..Default::default()
+        });
+    }
+    let widths: Vec<f64> = rows.windows(2).map(|w| w[1].0 - w[0].0).collect();
+    if widths.iter().any(|w| *w <= 0.0) {
+        return Err(
+            "the first column holds neither consecutive channel numbers nor increasing energies"
+                .to_string(),
+        );
+    }
+    let points: Vec<(f64, f64)> = rows
+        .iter()
+        .enumerate()
+        .map(|(i, r)| (i as f64, r.0))
+        .collect();
+    let calibration = fit_polynomial(&points, 2).ok_or("cannot fit the energy calibration")?;
+    // Energies off the calibration by half a channel are not those of consecutive channels
+    let narrowest = widths.iter().copied().fold(f64::INFINITY, f64::min);
+    if let Some(k) = points
+        .iter()
+        .position(|(i, e)| (polynomial(&calibration, *i) - e).abs() > narrowest / 2.0)
+    {
+        return Err(format!(
+            "line {}: the energy {} keV does not follow a quadratic calibration",
+            lines[k], rows[k].0
+        ));
+    }
+    Ok(Spectrum {
+        counts: counts.collect(),
+        calibration,
+        calibration_uncertainty: 0.0,
+        live_time: None,
+        real_time: None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::assert_close;
+
+    #[test]
+    fn read_spe_sections() {
+        let text = "$SPEC_ID:\ntest\n$MEAS_TIM:\n3600 3650\n$DATA:\n0 3\n5\n7\n0\n2\n\
+                    $ENER_FIT:\n0.3 0.25\n$MCA_CAL:\n3\n3.0E-01 2.5E-01 1.0E-06 keV\n";
+        let spectrum = read_spe(text).unwrap();
+        assert_eq!(spectrum.counts, [5.0, 7.0, 0.0, 2.0]);
+        assert_eq!(spectrum.calibration, [0.3, 0.25, 1e-6]);
+        assert_eq!(spectrum.live_time, Some(3600.0));
+        assert_eq!(spectrum.real_time, Some(3650.0));
+        // $ENER_FIT is the fallback calibration
+        let text = text.split("$MCA_CAL:").next().unwrap();
+        assert_eq!(read_spe(text).unwrap().calibration, [0.3, 0.25]);
+        assert!(read_spe("$DATA:\n0 3\n5\n7\n").is_err());
+        assert!(read_spe("$DATA:\n3 0\n5\n7\n").is_err());
+        assert!(read_spe("$DATA:\n0 4294967295\n5\n7\n").is_err());
+        assert!(read_spe("$SPEC_ID:\ntest\n").is_err());
+    }
+
+    /// A .Chn file of the given counts, live and real times in ticks of 20 ms, with a
+    /// quadratic calibration.
+    fn chn(counts: &[u32], live: u32, real: u32, calibration: [f32; 3]) -> Vec<u8> {
+        let mut bytes = vec![0; 32];
+        bytes[0..2].copy_from_slice(&0xffffu16.to_le_bytes());
+        bytes[8..12].copy_from_slice(&real.to_le_bytes());
+        bytes[12..16].copy_from_slice(&live.to_le_bytes());
+        bytes[30..32].copy_from_slice(&(counts.len() as u16).to_le_bytes());
+        for c in counts {
+            bytes.extend(c.to_le_bytes());
+        }
+        bytes.extend((-102i16).to_le_bytes());
+        bytes.extend([0, 0]);
+        for c in calibration {
+            bytes.extend(c.to_le_bytes());
+        }
+        bytes
+    }
+
+    #[test]
+    fn read_chn_counts_times_and_calibration() {
+        let spectrum = read_chn(&chn(&[1, 0, 30], 180000, 182500, [0.5, 0.25, 0.0])).unwrap();
+        assert_eq!(spectrum.counts, [1.0, 0.0, 30.0]);
+        assert_eq!(spectrum.calibration, [0.5, 0.25, 0.0]);
+        assert_close(spectrum.live_time.unwrap(), 3600.0);
+        assert_close(spectrum.real_time.unwrap(), 3650.0);
+        // A zero calibration is none
+        let uncalibrated = read_chn(&chn(&[1, 2], 0, 0, [0.0; 3])).unwrap();
+        assert!(uncalibrated.calibration.is_empty());
+        assert!(read_chn(&chn(&[], 0, 0, [0.5, 0.25, 0.0])).is_err());
+        assert!(read_chn(&chn(&[1, 2], 0, 0, [0.0; 3])[..36]).is_err());
+        assert!(read_chn(b"not a spectrum").is_err());
+    }
+
+    #[test]
+    fn read_n42_counted_zeroes() {
+        let text = r#"<?xml version="1.0"?>
+<RadInstrumentData xmlns="http://physics.nist.gov/N42/2011/N42">
+  <EnergyCalibration id="ec"><CoefficientValues>0.3 0.25 0</CoefficientValues></EnergyCalibration>
+  <RadMeasurement id="m">
+    <RealTimeDuration>PT1H50S</RealTimeDuration>
+    <Spectrum id="s" energyCalibrationReference="ec">
+      <LiveTimeDuration>PT3600S</LiveTimeDuration>
+      <ChannelData compressionCode="CountedZeroes">5 0 3 7 0 2</ChannelData>
+    </Spectrum>
+  </RadMeasurement>
+</RadInstrumentData>"#;
+        let spectrum = read_n42(text).unwrap();
+        assert_eq!(spectrum.counts, [5.0, 0.0, 0.0, 0.0, 7.0, 0.0, 0.0]);
+        assert_eq!(spectrum.calibration, [0.3, 0.25, 0.0]);
+        assert_eq!(spectrum.live_time, Some(3600.0));
+        assert_eq!(spectrum.real_time, Some(3650.0));
+        assert!(read_n42(&text.replace("0 2<", "0<")).is_err());
+        assert!(read_n42(&text.replace("5 0 3 7 0 2", "")).is_err());
+        assert!(read_n42(&text.replace("0 3", "0 1e12")).is_err());
+        assert_eq!(iso_duration("PT1H2M3.5S"), Some(3723.5));
+        assert_eq!(iso_duration("1 h"), None);
+    }
+
+    #[test]
+    fn read_csv_channels() {
+        let spectrum = read_csv("channel,counts\n0,5\n1,7\n2,9\n").unwrap();
+        assert_eq!(spectrum.counts, [5.0, 7.0, 9.0]);
+        assert!(spectrum.calibration.is_empty());
+        // Channels from 1 keep their number
+        let spectrum = read_csv("# from an MCA\n1;5\n2;7\n\n3;9\n").unwrap();
+        assert_eq!(spectrum.counts, [0.0, 5.0, 7.0, 9.0]);
+        assert!(spectrum.calibration.is_empty());
+        assert!(read_csv("4000000000,5\n4000000001,7\n").is_err());
+    }
+
+    #[test]
+    fn read_csv_energies() {
+        let mut text = "energy counts\n".to_string();
+        for i in 0..10 {
+            let x = i as f64;
+            text += &format!("{} {i}\n", 0.3 + 0.25 * x + 1e-4 * x * x);
+        }
+        let spectrum = read_csv(&text).unwrap();
+        assert_eq!(spectrum.counts.len(), 10);
+        for (c, expected) in spectrum.calibration.iter().zip([0.3, 0.25, 1e-4]) {
+            assert_close(*c, expected);
+        }
+    }
+
+    #[test]
+    fn read_csv_rejects_malformed_rows() {
+        assert_eq!(
+            read_csv("energy,counts\n0.3,1\n0.55,2\nfoo,3\n0.8,4\n").err(),
+            Some("line 4: expected two numbers".to_string())
+        );
+        assert!(read_csv("0.3,1\n0.55,2\n0.5,3\n").is_err());
+        assert_eq!(
+            read_csv("0.3,1\n0.55,2\n10,3\n10.1,4\n").err(),
+            Some("line 1: the energy 0.3 keV does not follow a quadratic calibration".to_string())
+        );
+        assert!(read_csv("energy,counts\n").is_err());
+    }
+}
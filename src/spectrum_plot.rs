@@ -0,0 +1,361 @@
+//! Plots to check candidates by eye: a measured spectrum against energy with the photon
+//! records of each candidate drawn as sticks, and the records of a single candidate on a
+//! logarithmic intensity scale. Both shade the windows of the query lines.
+
+use crate::app::RadiationType;
+use crate::database::TransitionResult;
+use crate::fit::polynomial;
+use crate::query_parser::Energy;
+use crate::search::{Candidate, SearchResult};
+use crate::spectrum::Spectrum;
+use egui::Color32;
+use egui_plot::{Corner, Legend, Line, MarkerShape, Plot, PlotPoints, Points, Polygon, VLine};
+
+/// Color of the `k`-th candidate, as egui_plot picks them.
+fn candidate_color(k: usize) -> Color32 {
+    let golden_ratio = (5.0_f32.sqrt() - 1.0) / 2.0;
+    egui::epaint::Hsva::new(k as f32 * golden_ratio, 0.85, 0.5, 1.0).into()
+}
+
+/// Intensity per 100 decays as shown in the results table.
+fn intensity_text(intensity: f64) -> String {
+    match intensity {
+        i if i == 0.0 || i >= 0.01 => format!("{i:.3}%"),
+        i => format!("{i:.2e}%"),
+    }
+}
+
+/// Hover text of a record: type, energy and intensity.
+fn record_text(r: &TransitionResult) -> String {
+    format!(
+        "{} {:.3} keV, {}{}",
+        r.t.radiation_type,
+        r.t.energy,
+        intensity_text(r.t.intensity),
+        if r.found { ", matched" } else { "" }
+    )
+}
+
+/// Description of a stick shown when hovering it: name of the candidate, energy and text.
+type StickLabel = (String, f64, String);
+
+/// Label of the item `name` nearest to `x`, for the plot hover text.
+fn nearest_label<'a>(labels: &'a [StickLabel], name: &str, x: f64) -> Option<&'a str> {
+    labels
+        .iter()
+        .filter(|l| l.0 == name)
+        .min_by(|a, b| (a.1 - x).abs().total_cmp(&(b.1 - x).abs()))
+        .map(|l| l.2.as_str())
+}
+
+#[derive(Default, serde::Deserialize, serde::Serialize)]
+#[serde(default)]
+pub struct SpectrumPlot {
+    /// Counts on a logarithmic scale, to see weak peaks next to strong ones.
+    log_counts: bool,
+}
+
+impl SpectrumPlot {
+    /// Shows `spectrum`, in keV when `calibration` is given (in channels otherwise), with the
+    /// query windows and the candidates of `results` overlaid.
+    pub fn show(
+        &mut self,
+        ui: &mut egui::Ui,
+        height: f32,
+        spectrum: &Spectrum,
+        calibration: &[f64],
+        energies: &[Energy],
+        results: Option<&SearchResult>,
+    ) {
+        if spectrum.counts.is_empty() {
+            ui.weak("the spectrum has no channels");
+            return;
+        }
+        let calibrated = !calibration.is_empty();
+        ui.horizontal(|ui| {
+            ui.checkbox(&mut self.log_counts, "log counts");
+            match calibrated {
+                true => ui.weak("click a decay in the legend to hide or show its lines"),
+                false => ui.weak("no energy calibration, see Spectrum > Energy calibration"),
+            };
+        });
+        let log_counts = self.log_counts;
+        let y = move |counts: f64| match log_counts {
+            true => counts.max(1.0).log10(),
+            false => counts,
+        };
+        let x: Vec<f64> = (0..spectrum.counts.len())
+            .map(|i| match calibrated {
+                true => polynomial(calibration, i as f64),
+                false => i as f64,
+            })
+            .collect();
+        let counts: Vec<[f64; 2]> = x
+            .iter()
+            .zip(&spectrum.counts)
+            .map(|(x, c)| [*x, y(*c)])
+            .collect();
+        let (x_min, x_max) = (x[0].min(x[x.len() - 1]), x[0].max(x[x.len() - 1]));
+        let max_counts = spectrum.counts.iter().copied().fold(1.0, f64::max);
+        // Highest counts within a few channels of `e`, the height of a peak there
+        let peak_height = |e: f64| {
+            let i = x.partition_point(|x| *x < e);
+            let window = i.saturating_sub(3)..(i + 4).min(x.len());
+            spectrum.counts[window].iter().copied().fold(0.0, f64::max)
+        };
+
+        let mut labels: Vec<StickLabel> = vec![];
+        let mut sticks = vec![];
+        for (k, c) in results
+            .filter(|_| calibrated)
+            .map_or(&[][..], |r| &r.candidates[..])
+            .iter()
+            .enumerate()
+        {
+            let records = photon_records(c, x_min, x_max);
+            // Scaled so that the strongest matched line reaches the top of its peak
+            let reference = records
+                .iter()
+                .filter(|r| r.found)
+                .max_by(|a, b| a.t.intensity.total_cmp(&b.t.intensity));
+            let scale = match reference {
+                Some(r) => peak_height(r.t.energy) / r.t.intensity,
+                None => {
+                    let strongest = records.iter().map(|r| r.t.intensity).fold(0.0, f64::max);
+                    max_counts / strongest
+                }
+            };
+            let name = c.decay.to_string();
+            let (mut matched, mut unmatched) = (vec![], vec![]);
+            for r in &records {
+                let point = [r.t.energy, y(r.t.intensity * scale)];
+                match r.found {
+                    true => matched.push(point),
+                    false => unmatched.push(point),
+                }
+                labels.push((name.clone(), r.t.energy, record_text(r)));
+            }
+            let color = candidate_color(k);
+            sticks.push(
+                Points::new(matched)
+                    .name(&name)
+                    .color(color)
+                    .shape(MarkerShape::Diamond)
+                    .radius(4.0)
+                    .stems(y(0.0) as f32),
+            );
+            sticks.push(
+                Points::new(unmatched)
+                    .name(&name)
+                    .color(color)
+                    .radius(2.0)
+                    .stems(y(0.0) as f32),
+            );
+        }
+
+        let window_color = ui.visuals().selection.bg_fill;
+        let top = y(max_counts);
+        let counts_color = ui.visuals().text_color();
+        let unit = if calibrated { "keV" } else { "channel" };
+        Plot::new("spectrum_plot")
+            .height(height)
+            .legend(Legend::default().position(Corner::RightTop))
+            .x_axis_label(match calibrated {
+                true => "Energy (keV)",
+                false => "Channel",
+            })
+            .y_axis_label("Counts")
+            .y_axis_formatter(move |mark, _, _| match log_counts {
+                true => format!("{}", 10f64.powf(mark.value).round()),
+                false => format!("{}", mark.value),
+            })
+            .label_formatter(move |name, point| match name {
+                "" => format!("{:.2} {unit}", point.x),
+                "counts" => {
+                    let counts = match log_counts {
+                        true => 10f64.powf(point.y),
+                        false => point.y,
+                    };
+                    format!("{:.2} {unit}\n{counts:.0} counts", point.x)
+                }
+                _ => match nearest_label(&labels, name, point.x) {
+                    Some(label) => format!("{name}\n{label}"),
+                    None => format!("{name}\n{:.2} {unit}", point.x),
+                },
+            })
+            .show(ui, |plot_ui| {
+                plot_ui.line(
+                    Line::new(PlotPoints::new(counts))
+                        .name("counts")
+                        .color(counts_color),
+                );
+                if calibrated {
+                    for e in energies {
+                        plot_ui.polygon(
+                            Polygon::new(PlotPoints::new(vec![
+                                [e.lteb, 0.0],
+                                [e.uteb, 0.0],
+                                [e.uteb, top],
+                                [e.lteb, top],
+                            ]))
+                            .name("query windows")
+                            .fill_color(window_color.gamma_multiply(0.5))
+                            .stroke(egui::Stroke::NONE),
+                        );
+                        // Narrow windows are hardly visible before zooming in
+                        plot_ui.vline(
+                            VLine::new((e.lteb + e.uteb) / 2.0)
+                                .name("query windows")
+                                .color(window_color),
+                        );
+                    }
+                }
+                for points in sticks {
+                    plot_ui.points(points);
+                }
+            });
+    }
+}
+
+/// Gamma and X-ray records of the candidate `c` with an intensity, between `min` and `max` keV.
+fn photon_records(c: &Candidate, min: f64, max: f64) -> Vec<&TransitionResult> {
+    c.transitions
+        .iter()
+        .filter(|r| {
+            matches!(
+                r.t.radiation_type,
+                RadiationType::Gamma | RadiationType::XRay
+            )
+        })
+        .filter(|r| r.t.intensity > 0.0 && (min..=max).contains(&r.t.energy))
+        .collect()
+}
+
+#[derive(Default, serde::Deserialize, serde::Serialize)]
+#[serde(default)]
+pub struct LinePlot {
+    /// Index of the candidate shown.
+    #[serde(skip)]
+    selected: usize,
+}
+
+impl LinePlot {
+    /// Shows the records with a known intensity of a candidate of `results`, chosen in a combo
+    /// box, with the windows of the query `energies`. A strong record left unmatched argues
+    /// against the candidate.
+    pub fn show(
+        &mut self,
+        ui: &mut egui::Ui,
+        height: f32,
+        energies: &[Energy],
+        results: &SearchResult,
+    ) {
+        if results.candidates.is_empty() {
+            return;
+        }
+        if self.selected >= results.candidates.len() {
+            self.selected = 0;
+        }
+        ui.horizontal(|ui| {
+            ui.label("Lines of");
+            egui::ComboBox::from_id_source("line_plot_candidate")
+                .selected_text(results.candidates[self.selected].decay.to_string())
+                .show_ui(ui, |ui| {
+                    for (k, c) in results.candidates.iter().enumerate() {
+                        ui.selectable_value(&mut self.selected, k, c.decay.to_string());
+                    }
+                });
+        });
+        let c = &results.candidates[self.selected];
+        let records: Vec<&TransitionResult> = c
+            .transitions
+            .iter()
+            .filter(|r| r.t.intensity > 0.0)
+            .collect();
+        if records.is_empty() {
+            ui.weak("none of its records has a known intensity");
+            return;
+        }
+
+        // Decades of intensity, with the stems starting below the weakest record
+        let (weakest, strongest) = records.iter().fold((f64::INFINITY, 0.0_f64), |(l, h), r| {
+            (l.min(r.t.intensity), h.max(r.t.intensity))
+        });
+        let bottom = (weakest.log10() - 0.5).floor();
+        let top = strongest.log10() + 0.5;
+        let (mut matched, mut unmatched) = (vec![], vec![]);
+        let mut labels: Vec<StickLabel> = vec![];
+        for r in &records {
+            let point = [r.t.energy, r.t.intensity.log10()];
+            let name = match r.found {
+                true => {
+                    matched.push(point);
+                    "matched"
+                }
+                false => {
+                    unmatched.push(point);
+                    "not matched"
+                }
+            };
+            labels.push((name.to_string(), r.t.energy, record_text(r)));
+        }
+
+        let window_color = ui.visuals().selection.bg_fill;
+        let color = candidate_color(self.selected);
+        let unmatched_color = ui.visuals().weak_text_color();
+        // One plot per candidate, so that each one starts zoomed out on its own lines
+        Plot::new(("line_plot", &c.decay.dataset))
+            .height(height)
+            .legend(Legend::default().position(Corner::RightTop))
+            .x_axis_label("Energy (keV)")
+            .y_axis_label("Intensity per 100 decays")
+            // Only the decades are labelled
+            .y_axis_formatter(
+                |mark, _, _| match (mark.value - mark.value.round()).abs() < 1e-6 {
+                    true => intensity_text(10f64.powf(mark.value.round())),
+                    false => String::new(),
+                },
+            )
+            .label_formatter(
+                move |name, point| match nearest_label(&labels, name, point.x) {
+                    Some(label) => label.to_string(),
+                    None => format!("{:.2} keV", point.x),
+                },
+            )
+            .show(ui, |plot_ui| {
+                for e in energies {
+                    plot_ui.polygon(
+                        Polygon::new(PlotPoints::new(vec![
+                            [e.lteb, bottom],
+                            [e.uteb, bottom],
+                            [e.uteb, top],
+                            [e.lteb, top],
+                        ]))
+                        .name("query windows")
+                        .fill_color(window_color.gamma_multiply(0.5))
+                        .stroke(egui::Stroke::NONE),
+                    );
+                    plot_ui.vline(
+                        VLine::new((e.lteb + e.uteb) / 2.0)
+                            .name("query windows")
+                            .color(window_color),
+                    );
+                }
+                plot_ui.points(
+                    Points::new(unmatched)
+                        .name("not matched")
+                        .color(unmatched_color)
+                        .radius(2.0)
+                        .stems(bottom as f32),
+                );
+                plot_ui.points(
+                    Points::new(matched)
+                        .name("matched")
+                        .color(color)
+                        .shape(MarkerShape::Diamond)
+                        .radius(4.0)
+                        .stems(bottom as f32),
+                );
+            });
+    }
+}
@@ -0,0 +1,54 @@
+//! Helpers shared by the unit tests.
+
+use crate::app::RadiationType;
+use crate::database::{EnergyQualifier, Transition};
+use crate::nuclide::{Decay, DecayMode, Nuclide};
+use crate::query_parser::{Energy, Modifier};
+
+/// Asserts that `a` equals `b` to within 1e-9, relative to `b` when it is larger than 1.
+#[track_caller]
+pub fn assert_close(a: f64, b: f64) {
+    assert!((a - b).abs() <= 1e-9 * b.abs().max(1.0), "{a} != {b}");
+}
+
+/// β- decay of the ground state of (`z`, `a`), labelled `dataset`, of unknown half-life.
+pub fn decay(dataset: &str, z: u32, a: u32) -> Decay {
+    let nuclide = |atomic_number| Nuclide {
+        mass_number: a,
+        atomic_number,
+        level_energy: 0.0,
+    };
+    Decay {
+        parent: nuclide(z),
+        mode: DecayMode::BetaMinus,
+        daughter: nuclide(z + 1),
+        dataset: dataset.to_string(),
+        half_life: None,
+        branching_ratio: None,
+        fed_isomers: vec![],
+    }
+}
+
+/// Gamma record of `decay` at `energy` keV, matching within ±0.5 keV.
+pub fn gamma(decay: &Decay, energy: f64, intensity: f64) -> Transition {
+    Transition {
+        decay: decay.clone(),
+        radiation_type: RadiationType::Gamma,
+        energy,
+        uncertainty: 0.5,
+        qualifier: EnergyQualifier::Measured,
+        intensity,
+        lteb: energy - 0.5,
+        uteb: energy + 0.5,
+        mean_energy: None,
+        line: String::new(),
+    }
+}
+
+/// Query gamma line at `energy` keV, within ±1 keV.
+pub fn line(energy: f64, modifier: Modifier) -> Energy {
+    Energy {
+        radiation_type: Some(RadiationType::Gamma),
+        ..Energy::new(energy - 1.0, energy + 1.0, modifier)
+    }
+}